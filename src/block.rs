@@ -0,0 +1,108 @@
+use std::ops::{Range, RangeInclusive};
+
+use crate::coords::Coordinates;
+use crate::screen::{byte_at_col, display_col};
+
+/// Rectangle of text selected across rows, between the corner where it started and
+/// the one that moves. Corners are (column on the screen, row), so that the block
+/// stays straight over tabs and can go past the end of short rows. A block with no
+/// columns is a column of cursors, typing there inserts on every row.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub anchor: Coordinates<usize>,
+    pub corner: Coordinates<usize>,
+}
+
+impl Block {
+    pub fn new(at: Coordinates<usize>) -> Self {
+        Self {
+            anchor: at,
+            corner: at,
+        }
+    }
+
+    pub fn rows(&self) -> RangeInclusive<usize> {
+        self.anchor.y().min(self.corner.y())..=self.anchor.y().max(self.corner.y())
+    }
+
+    pub fn columns(&self) -> Range<usize> {
+        self.anchor.x().min(self.corner.x())..self.anchor.x().max(self.corner.x())
+    }
+
+    /// Block without columns at `col`, on the same rows.
+    pub fn collapsed(&self, col: usize) -> Self {
+        Self {
+            anchor: Coordinates::new(col, self.anchor.y()),
+            corner: Coordinates::new(col, self.corner.y()),
+        }
+    }
+}
+
+/// Bytes of `row` drawn in `columns`, with the tabs that are partly inside them.
+pub fn byte_range(row: &str, columns: &Range<usize>, tab_width: usize) -> Range<usize> {
+    let start = byte_at_col(row, columns.start, tab_width);
+    let end = match columns.end {
+        _ if columns.is_empty() => start,
+        end => {
+            let last = byte_at_col(row, end - 1, tab_width);
+            row[last..]
+                .chars()
+                .next()
+                .map_or(last, |ch| last + ch.len_utf8())
+        }
+    };
+    start..end.max(start)
+}
+
+/// Adds spaces to the end of `row` until it reaches column `col`, and returns the
+/// byte drawn there.
+pub fn pad_to(row: &mut String, col: usize, tab_width: usize) -> usize {
+    let width = display_col(row, row.len(), tab_width);
+    if width < col {
+        row.push_str(&" ".repeat(col - width));
+    }
+    byte_at_col(row, col, tab_width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_ranges() {
+        assert_eq!(byte_range("abcdef", &(1..3), 4), 1..3);
+        assert_eq!(byte_range("abcdef", &(2..2), 4), 2..2);
+        assert_eq!(byte_range("ab", &(1..5), 4), 1..2);
+        assert_eq!(byte_range("ab", &(3..5), 4), 2..2);
+        assert_eq!(byte_range("héllo", &(1..3), 4), 1..4);
+        // the tab is drawn in columns 1 to 3
+        assert_eq!(byte_range("a\tb", &(2..3), 4), 1..2);
+        assert_eq!(byte_range("a\tb", &(0..2), 4), 0..2);
+        assert_eq!(byte_range("a\tb", &(4..5), 4), 2..3);
+    }
+
+    #[test]
+    fn padding() {
+        let mut row = "ab".to_string();
+        assert_eq!(pad_to(&mut row, 4, 4), 4);
+        assert_eq!(row, "ab  ");
+        assert_eq!(pad_to(&mut row, 1, 4), 1);
+        assert_eq!(row, "ab  ");
+
+        let mut row = "é\t".to_string();
+        assert_eq!(pad_to(&mut row, 2, 4), 2);
+        assert_eq!(pad_to(&mut row, 6, 4), 5);
+        assert_eq!(row, "é\t  ");
+    }
+
+    #[test]
+    fn rows_and_columns() {
+        let mut block = Block::new(Coordinates::new(5, 3));
+        block.corner = Coordinates::new(2, 1);
+        assert_eq!(block.rows(), 1..=3);
+        assert_eq!(block.columns(), 2..5);
+        let collapsed = block.collapsed(4);
+        assert_eq!(collapsed.rows(), 1..=3);
+        assert!(collapsed.columns().is_empty());
+    }
+}
@@ -0,0 +1,67 @@
+use crate::coords::Coordinates;
+use crate::highlight::TokenClass;
+
+const PAIRS: [(u8, u8); 3] = [(b'(', b')'), (b'[', b']'), (b'{', b'}')];
+
+pub fn is_bracket(ch: char) -> bool {
+    PAIRS
+        .iter()
+        .any(|&(open, close)| ch == open as char || ch == close as char)
+}
+
+/// Position of the bracket matching the one at `at`, `None` when it is unmatched.
+/// When `tokens` tells where the strings and comments are, brackets inside them
+/// are only matched with each other.
+pub fn matching_bracket(
+    rows: &[String],
+    tokens: &[Vec<TokenClass>],
+    at: Coordinates<usize>,
+) -> Option<Coordinates<usize>> {
+    let bracket = *rows.get(at.y())?.as_bytes().get(at.x())?;
+    let &(open, close) = PAIRS
+        .iter()
+        .find(|&&(open, close)| bracket == open || bracket == close)?;
+    let forward = bracket == open;
+
+    let is_code = |x: usize, y: usize| {
+        !matches!(
+            tokens.get(y).and_then(|classes| classes.get(x)),
+            Some(TokenClass::String | TokenClass::Comment)
+        )
+    };
+    let in_code = is_code(at.x(), at.y());
+
+    let mut depth = 0;
+    let mut visit = |x: usize, y: usize, byte: u8| {
+        if is_code(x, y) != in_code {
+            return false;
+        }
+        if byte == bracket {
+            depth += 1;
+        } else if byte == open || byte == close {
+            depth -= 1;
+        }
+        depth == 0
+    };
+
+    if forward {
+        for (y, row) in rows.iter().enumerate().skip(at.y()) {
+            let start = if y == at.y() { at.x() } else { 0 };
+            for (x, &byte) in row.as_bytes().iter().enumerate().skip(start) {
+                if visit(x, y, byte) {
+                    return Some(Coordinates::new(x, y));
+                }
+            }
+        }
+    } else {
+        for (y, row) in rows.iter().enumerate().take(at.y() + 1).rev() {
+            let end = if y == at.y() { at.x() + 1 } else { row.len() };
+            for (x, &byte) in row.as_bytes()[..end].iter().enumerate().rev() {
+                if visit(x, y, byte) {
+                    return Some(Coordinates::new(x, y));
+                }
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,161 @@
+use std::{path::Path, str::FromStr};
+
+use crate::goto::GoTo;
+
+pub const USAGE: &str = "\
+Usage: texty [OPTIONS] [[+LINE[:COL]] FILE[:LINE[:COL]]]...
+
+Opens each FILE in the editor, creating it on save if it does not exist.
+A position can be given as FILE:LINE[:COL] or as +LINE[:COL] before the file.
+When FILE is -, the buffer is read from stdin.
+
+Options:
+  -R, --readonly    Open the files in read-only mode
+      --pipe        Read the buffer from stdin and write it to stdout on quit
+      --keymap NAME Key bindings to use: default, vi or emacs
+  -h, --help        Print this help and exit
+  -V, --version     Print the version and exit";
+
+pub enum Command {
+    Edit(Args),
+    Help,
+    Version,
+}
+
+#[derive(Default)]
+pub struct Args {
+    pub files: Vec<FileArg>,
+    pub read_only: bool,
+    pub pipe: bool,
+    pub keymap: Keymap,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub enum Keymap {
+    #[default]
+    Default,
+    Vi,
+    Emacs,
+}
+
+impl FromStr for Keymap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Keymap::Default),
+            "vi" => Ok(Keymap::Vi),
+            "emacs" => Ok(Keymap::Emacs),
+            _ => Err(format!("unknown keymap '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FileArg {
+    pub path: String,
+    pub position: Option<GoTo>,
+}
+
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut parsed = Args::default();
+    let mut position: Option<GoTo> = None;
+    let mut only_files = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !only_files && arg.starts_with('-') && arg.len() > 1 {
+            match arg.as_str() {
+                "-R" | "--readonly" => parsed.read_only = true,
+                "--pipe" => parsed.pipe = true,
+                "--keymap" => {
+                    let name = args.next().ok_or("--keymap needs a name")?;
+                    parsed.keymap = name.parse()?;
+                }
+                "-h" | "--help" => return Ok(Command::Help),
+                "-V" | "--version" => return Ok(Command::Version),
+                "--" => only_files = true,
+                _ => return Err(format!("unknown option '{}'", arg)),
+            }
+        } else if let Some(line) = arg.strip_prefix('+').filter(|_| !only_files) {
+            let go_to = line
+                .parse::<GoTo>()
+                .map_err(|err| format!("invalid position '{}': {}", arg, err))?;
+            position = Some(go_to);
+        } else {
+            let mut file = if arg == "-" {
+                FileArg {
+                    path: arg,
+                    position: None,
+                }
+            } else {
+                split_position(&arg)?
+            };
+            if Path::new(&file.path).is_dir() {
+                return Err(format!("'{}' is a directory", file.path));
+            }
+            if let Some(go_to) = position.take() {
+                file.position = Some(go_to);
+            }
+            parsed.files.push(file);
+        }
+    }
+
+    if position.is_some() {
+        return Err("a +LINE position must be followed by a file".to_string());
+    }
+
+    if parsed.pipe {
+        if parsed.files.iter().any(|file| file.path != "-") {
+            return Err("--pipe reads from stdin and can't be used with files".to_string());
+        }
+        if parsed.files.is_empty() {
+            parsed.files.push(FileArg {
+                path: "-".to_string(),
+                position: None,
+            });
+        }
+    }
+
+    Ok(Command::Edit(parsed))
+}
+
+/// Splits `path:line[:col]` into the path and the position, unless a file with
+/// the whole name exists.
+fn split_position(arg: &str) -> Result<FileArg, String> {
+    let whole = FileArg {
+        path: arg.to_string(),
+        position: None,
+    };
+    if Path::new(arg).exists() {
+        return Ok(whole);
+    }
+
+    let trimmed = arg.strip_suffix(':').unwrap_or(arg);
+    let mut parts = trimmed.rsplitn(3, ':');
+    let last = parts.next();
+    let middle = parts.next();
+    let first = parts.next();
+
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|ch| ch.is_ascii_digit());
+
+    let (path, position) = match (first, middle, last) {
+        (Some(path), Some(line), Some(col)) if is_number(line) && is_number(col) => {
+            (path.to_string(), format!("{}:{}", line, col))
+        }
+        (_, Some(_), Some(line)) if is_number(line) => {
+            let path = trimmed[..trimmed.len() - line.len() - 1].to_string();
+            (path, line.to_string())
+        }
+        _ => return Ok(whole),
+    };
+
+    if path.is_empty() {
+        return Ok(whole);
+    }
+
+    Ok(FileArg {
+        path,
+        position: Some(position.parse()?),
+    })
+}
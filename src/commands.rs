@@ -0,0 +1,230 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Action that can be bound to a key, named like in `keys.toml`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Command {
+    Quit,
+    Save,
+    Find,
+    Open,
+    GoTo,
+    ToggleReadOnly,
+    NextFile,
+    PreviousFile,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    MoveWordLeft,
+    MoveWordRight,
+    MoveLineStart,
+    MoveLineEnd,
+    MovePageUp,
+    MovePageDown,
+    MoveFileStart,
+    MoveFileEnd,
+    MatchingBracket,
+    Newline,
+    Backspace,
+    Delete,
+    DeleteWordLeft,
+    DeleteWordRight,
+    ToggleComment,
+    MoveLinesUp,
+    MoveLinesDown,
+    DuplicateLines,
+    DeleteLines,
+    JoinLines,
+    Indent,
+    Outdent,
+    TrimWhitespace,
+    AddCursorAtNext,
+    AddCursorAbove,
+    AddCursorBelow,
+    ClearCursors,
+    BlockLeft,
+    BlockRight,
+    BlockUp,
+    BlockDown,
+    Copy,
+    Cut,
+    Paste,
+    Prompt,
+}
+
+impl Command {
+    pub const ALL: [Command; 47] = [
+        Command::Quit,
+        Command::Save,
+        Command::Find,
+        Command::Open,
+        Command::GoTo,
+        Command::ToggleReadOnly,
+        Command::NextFile,
+        Command::PreviousFile,
+        Command::MoveLeft,
+        Command::MoveRight,
+        Command::MoveUp,
+        Command::MoveDown,
+        Command::MoveWordLeft,
+        Command::MoveWordRight,
+        Command::MoveLineStart,
+        Command::MoveLineEnd,
+        Command::MovePageUp,
+        Command::MovePageDown,
+        Command::MoveFileStart,
+        Command::MoveFileEnd,
+        Command::MatchingBracket,
+        Command::Newline,
+        Command::Backspace,
+        Command::Delete,
+        Command::DeleteWordLeft,
+        Command::DeleteWordRight,
+        Command::ToggleComment,
+        Command::MoveLinesUp,
+        Command::MoveLinesDown,
+        Command::DuplicateLines,
+        Command::DeleteLines,
+        Command::JoinLines,
+        Command::Indent,
+        Command::Outdent,
+        Command::TrimWhitespace,
+        Command::AddCursorAtNext,
+        Command::AddCursorAbove,
+        Command::AddCursorBelow,
+        Command::ClearCursors,
+        Command::BlockLeft,
+        Command::BlockRight,
+        Command::BlockUp,
+        Command::BlockDown,
+        Command::Copy,
+        Command::Cut,
+        Command::Paste,
+        Command::Prompt,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Quit => "quit",
+            Command::Save => "save",
+            Command::Find => "find",
+            Command::Open => "open",
+            Command::GoTo => "goto",
+            Command::ToggleReadOnly => "readonly.toggle",
+            Command::NextFile => "file.next",
+            Command::PreviousFile => "file.previous",
+            Command::MoveLeft => "move.left",
+            Command::MoveRight => "move.right",
+            Command::MoveUp => "move.up",
+            Command::MoveDown => "move.down",
+            Command::MoveWordLeft => "move.word_left",
+            Command::MoveWordRight => "move.word_right",
+            Command::MoveLineStart => "move.line_start",
+            Command::MoveLineEnd => "move.line_end",
+            Command::MovePageUp => "move.page_up",
+            Command::MovePageDown => "move.page_down",
+            Command::MoveFileStart => "move.file_start",
+            Command::MoveFileEnd => "move.file_end",
+            Command::MatchingBracket => "move.matching_bracket",
+            Command::Newline => "edit.newline",
+            Command::Backspace => "edit.backspace",
+            Command::Delete => "edit.delete",
+            Command::DeleteWordLeft => "edit.delete_word_left",
+            Command::DeleteWordRight => "edit.delete_word_right",
+            Command::ToggleComment => "edit.toggle_comment",
+            Command::MoveLinesUp => "edit.move_lines_up",
+            Command::MoveLinesDown => "edit.move_lines_down",
+            Command::DuplicateLines => "edit.duplicate_lines",
+            Command::DeleteLines => "edit.delete_lines",
+            Command::JoinLines => "edit.join_lines",
+            Command::Indent => "edit.indent",
+            Command::Outdent => "edit.outdent",
+            Command::TrimWhitespace => "edit.trim_whitespace",
+            Command::AddCursorAtNext => "cursor.add_next",
+            Command::AddCursorAbove => "cursor.add_above",
+            Command::AddCursorBelow => "cursor.add_below",
+            Command::ClearCursors => "cursor.clear",
+            Command::BlockLeft => "block.left",
+            Command::BlockRight => "block.right",
+            Command::BlockUp => "block.up",
+            Command::BlockDown => "block.down",
+            Command::Copy => "edit.copy",
+            Command::Cut => "edit.cut",
+            Command::Paste => "edit.paste",
+            Command::Prompt => "command",
+        }
+    }
+
+    /// Whether the command runs at every cursor when there are several.
+    pub fn at_each_cursor(&self) -> bool {
+        matches!(
+            self,
+            Command::MoveLeft
+                | Command::MoveRight
+                | Command::MoveUp
+                | Command::MoveDown
+                | Command::MoveWordLeft
+                | Command::MoveWordRight
+                | Command::MoveLineStart
+                | Command::MoveLineEnd
+                | Command::Newline
+                | Command::Backspace
+                | Command::Delete
+                | Command::DeleteWordLeft
+                | Command::DeleteWordRight
+                | Command::Indent
+                | Command::Paste
+        )
+    }
+
+    /// Whether the other cursors are kept when the command runs at the main one only.
+    pub fn keeps_cursors(&self) -> bool {
+        matches!(
+            self,
+            Command::Quit
+                | Command::Save
+                | Command::ToggleReadOnly
+                | Command::AddCursorAtNext
+                | Command::AddCursorAbove
+                | Command::AddCursorBelow
+        )
+    }
+
+    /// Whether the block selection is kept, the commands that edit text do it in
+    /// every row of the block.
+    pub fn keeps_block(&self) -> bool {
+        matches!(
+            self,
+            Command::Quit
+                | Command::Save
+                | Command::ToggleReadOnly
+                | Command::BlockLeft
+                | Command::BlockRight
+                | Command::BlockUp
+                | Command::BlockDown
+                | Command::Backspace
+                | Command::Delete
+                | Command::Copy
+                | Command::Cut
+                | Command::Paste
+        )
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Command::ALL
+            .into_iter()
+            .find(|command| command.name() == s)
+            .ok_or_else(|| format!("unknown action '{}'", s))
+    }
+}
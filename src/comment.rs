@@ -0,0 +1,113 @@
+use crate::filetype::FileType;
+use crate::indent::leading_whitespace;
+
+/// How a file type comments out a line.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CommentSyntax {
+    Line(&'static str),
+    Block(&'static str, &'static str),
+}
+
+impl CommentSyntax {
+    /// Line comments are preferred, block comments are used for the file types
+    /// without them.
+    pub fn of(filetype: &FileType) -> Option<Self> {
+        match (filetype.line_comment, filetype.block_comment) {
+            (Some(start), _) => Some(CommentSyntax::Line(start)),
+            (None, Some((start, end))) => Some(CommentSyntax::Block(start, end)),
+            (None, None) => None,
+        }
+    }
+
+    pub fn is_commented(&self, row: &str) -> bool {
+        let text = row.trim();
+        match *self {
+            CommentSyntax::Line(start) => text.starts_with(start),
+            CommentSyntax::Block(start, end) => {
+                text.len() >= start.len() + end.len()
+                    && text.starts_with(start)
+                    && text.ends_with(end)
+            }
+        }
+    }
+
+    /// Comments `row` out with the marker at `column`. Returns the column and the
+    /// number of bytes added in front of the text.
+    pub fn comment(&self, row: &mut String, column: usize) -> (usize, isize) {
+        let (start, end) = match *self {
+            CommentSyntax::Line(start) => (start, None),
+            CommentSyntax::Block(start, end) => (start, Some(end)),
+        };
+        if let Some(end) = end {
+            row.truncate(row.trim_end().len());
+            row.push(' ');
+            row.push_str(end);
+        }
+        let marker = format!("{} ", start);
+        row.insert_str(column, &marker);
+        (column, marker.len() as isize)
+    }
+
+    /// Removes the comment markers of `row`, and the space after the opening one.
+    /// Returns the column the text moved to and the number of bytes removed in front
+    /// of it, as a negative number.
+    pub fn uncomment(&self, row: &mut String) -> (usize, isize) {
+        let column = leading_whitespace(row).len();
+        let (start, end) = match *self {
+            CommentSyntax::Line(start) => (start, None),
+            CommentSyntax::Block(start, end) => (start, Some(end)),
+        };
+        if let Some(end) = end {
+            let text_end = row.trim_end().len() - end.len();
+            let text_end = row[..text_end].trim_end().len().max(column + start.len());
+            row.truncate(text_end);
+        }
+        let mut removed = start.len();
+        if row[column + removed..].starts_with(' ') {
+            removed += 1;
+        }
+        row.replace_range(column..column + removed, "");
+        (column, -(removed as isize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: CommentSyntax = CommentSyntax::Line("//");
+    const BLOCK: CommentSyntax = CommentSyntax::Block("/*", "*/");
+
+    #[test]
+    fn line_comments() {
+        let mut row = "    let x = 1;".to_string();
+        assert_eq!(LINE.comment(&mut row, 4), (4, 3));
+        assert_eq!(row, "    // let x = 1;");
+        assert!(LINE.is_commented(&row));
+        assert_eq!(LINE.uncomment(&mut row), (4, -3));
+        assert_eq!(row, "    let x = 1;");
+        assert!(!LINE.is_commented(&row));
+
+        let mut row = "  //x".to_string();
+        assert_eq!(LINE.uncomment(&mut row), (2, -2));
+        assert_eq!(row, "  x");
+    }
+
+    #[test]
+    fn block_comments() {
+        let mut row = "  a { b }  ".to_string();
+        assert_eq!(BLOCK.comment(&mut row, 2), (2, 3));
+        assert_eq!(row, "  /* a { b } */");
+        assert!(BLOCK.is_commented(&row));
+        assert_eq!(BLOCK.uncomment(&mut row), (2, -3));
+        assert_eq!(row, "  a { b }");
+
+        let mut row = "/*a*/".to_string();
+        BLOCK.uncomment(&mut row);
+        assert_eq!(row, "a");
+        let mut row = "/* */".to_string();
+        BLOCK.uncomment(&mut row);
+        assert_eq!(row, "");
+        assert!(!BLOCK.is_commented("/*/"));
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use toml::{Table, Value};
+
+use crate::indent::IndentStyle;
+
+/// Path of a file in the configuration directory, `$XDG_CONFIG_HOME/texty`
+/// or `~/.config/texty`.
+pub fn config_file(name: &str) -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("texty").join(name))
+}
+
+/// Options that can be set in `config.toml` or with `:set`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub greeting: String,
+    pub message_timeout: Duration,
+    pub tab_width: usize,
+    pub line_numbers: bool,
+    pub theme: String,
+    pub auto_indent: bool,
+    pub auto_pairs: bool,
+    /// `None` to guess it from the file
+    pub indent: Option<IndentStyle>,
+    pub trim_trailing_whitespace: bool,
+    /// Text after this column is highlighted, 0 to turn it off
+    pub max_line_length: usize,
+    /// Runs `edit.trim_whitespace` before saving
+    pub trim_on_save: bool,
+    pub show_whitespace: bool,
+    /// Clicks and scrolling go to the editor instead of the terminal
+    pub mouse: bool,
+}
+
+pub const OPTIONS: [&str; 13] = [
+    "greeting",
+    "message_timeout",
+    "tab_width",
+    "line_numbers",
+    "theme",
+    "auto_indent",
+    "auto_pairs",
+    "indent",
+    "trim_trailing_whitespace",
+    "max_line_length",
+    "trim_on_save",
+    "show_whitespace",
+    "mouse",
+];
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            greeting: "My editor -- version 1".to_string(),
+            message_timeout: Duration::new(1, 0),
+            tab_width: 8,
+            line_numbers: false,
+            theme: "default".to_string(),
+            auto_indent: true,
+            auto_pairs: true,
+            indent: None,
+            trim_trailing_whitespace: false,
+            max_line_length: 0,
+            trim_on_save: false,
+            show_whitespace: false,
+            mouse: true,
+        }
+    }
+}
+
+impl Settings {
+    pub fn set(&mut self, name: &str, value: &Value) -> Result<(), String> {
+        let invalid = || format!("invalid value {} for '{}'", value, name);
+
+        match option_name(name)? {
+            "greeting" => self.greeting = value.as_str().ok_or_else(invalid)?.to_string(),
+            "message_timeout" => {
+                let secs = match value {
+                    Value::Integer(secs) => *secs as f64,
+                    Value::Float(secs) => *secs,
+                    _ => return Err(invalid()),
+                };
+                self.message_timeout = Duration::try_from_secs_f64(secs).map_err(|_| invalid())?;
+            }
+            "tab_width" => {
+                self.tab_width = value
+                    .as_integer()
+                    .filter(|width| (1..=16).contains(width))
+                    .ok_or_else(invalid)? as usize
+            }
+            "line_numbers" => self.line_numbers = value.as_bool().ok_or_else(invalid)?,
+            "theme" => self.theme = value.as_str().ok_or_else(invalid)?.to_string(),
+            "auto_indent" => self.auto_indent = value.as_bool().ok_or_else(invalid)?,
+            "auto_pairs" => self.auto_pairs = value.as_bool().ok_or_else(invalid)?,
+            "indent" => {
+                self.indent = match value {
+                    Value::String(style) if style == "auto" => None,
+                    Value::String(style) if style == "tabs" => Some(IndentStyle::Tabs),
+                    Value::Integer(width @ 1..=16) => Some(IndentStyle::Spaces(*width as usize)),
+                    _ => return Err(invalid()),
+                }
+            }
+            "trim_trailing_whitespace" => {
+                self.trim_trailing_whitespace = value.as_bool().ok_or_else(invalid)?
+            }
+            "max_line_length" => {
+                self.max_line_length = value
+                    .as_integer()
+                    .filter(|length| *length >= 0)
+                    .ok_or_else(invalid)? as usize
+            }
+            "trim_on_save" => self.trim_on_save = value.as_bool().ok_or_else(invalid)?,
+            "show_whitespace" => self.show_whitespace = value.as_bool().ok_or_else(invalid)?,
+            "mouse" => self.mouse = value.as_bool().ok_or_else(invalid)?,
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<Value, String> {
+        let value = match option_name(name)? {
+            "greeting" => Value::String(self.greeting.clone()),
+            "message_timeout" => Value::Float(self.message_timeout.as_secs_f64()),
+            "tab_width" => Value::Integer(self.tab_width as i64),
+            "line_numbers" => Value::Boolean(self.line_numbers),
+            "theme" => Value::String(self.theme.clone()),
+            "auto_indent" => Value::Boolean(self.auto_indent),
+            "auto_pairs" => Value::Boolean(self.auto_pairs),
+            "indent" => match self.indent {
+                None => Value::String("auto".to_string()),
+                Some(IndentStyle::Tabs) => Value::String("tabs".to_string()),
+                Some(IndentStyle::Spaces(width)) => Value::Integer(width as i64),
+            },
+            "trim_trailing_whitespace" => Value::Boolean(self.trim_trailing_whitespace),
+            "max_line_length" => Value::Integer(self.max_line_length as i64),
+            "trim_on_save" => Value::Boolean(self.trim_on_save),
+            "show_whitespace" => Value::Boolean(self.show_whitespace),
+            "mouse" => Value::Boolean(self.mouse),
+            _ => unreachable!(),
+        };
+        Ok(value)
+    }
+
+    /// Applies the options of a table, returning the ones that could not be set.
+    pub fn apply(&mut self, table: &Table) -> Vec<String> {
+        table
+            .iter()
+            .filter_map(|(name, value)| self.set(name, value).err())
+            .collect()
+    }
+
+    /// Applies an assignment typed by the user: `name=value`, `name` to turn an
+    /// option on or `noname` to turn it off.
+    pub fn set_from_str(&mut self, assignment: &str) -> Result<(), String> {
+        let assignment = assignment.trim();
+        match assignment.split_once('=') {
+            Some((name, value)) => self.set(name.trim(), &parse_value(value.trim())),
+            None => match assignment.strip_prefix("no") {
+                Some(name) if option_name(assignment).is_err() => {
+                    self.set(name, &Value::Boolean(false))
+                }
+                _ => self.set(assignment, &Value::Boolean(true)),
+            },
+        }
+    }
+}
+
+/// Options can be written with or without underscores, `tabwidth` is `tab_width`.
+fn option_name(name: &str) -> Result<&'static str, String> {
+    let wanted = name.replace('_', "");
+    OPTIONS
+        .into_iter()
+        .find(|option| option.replace('_', "") == wanted)
+        .ok_or_else(|| format!("unknown option '{}'", name))
+}
+
+fn parse_value(value: &str) -> Value {
+    if let Ok(boolean) = value.parse() {
+        Value::Boolean(boolean)
+    } else if let Ok(integer) = value.parse() {
+        Value::Integer(integer)
+    } else if let Ok(float) = value.parse() {
+        Value::Float(float)
+    } else {
+        let unquoted = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        Value::String(unquoted.to_string())
+    }
+}
+
+/// Contents of `config.toml`: the options for every file and the `[filetype.NAME]`
+/// sections that override them for a kind of file.
+#[derive(Default)]
+pub struct Config {
+    pub settings: Settings,
+    filetypes: HashMap<String, Table>,
+}
+
+impl Config {
+    /// Reads `config.toml`, returning the problems found in it.
+    pub fn load() -> (Self, Vec<String>) {
+        let Some(path) = config_file("config.toml") else {
+            return (Self::default(), vec![]);
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == ErrorKind::NotFound => (Self::default(), vec![]),
+            Err(err) => (
+                Self::default(),
+                vec![format!("can't read {}: {}", path.display(), err)],
+            ),
+        }
+    }
+
+    pub fn parse(text: &str) -> (Self, Vec<String>) {
+        let mut config = Self::default();
+        let mut table = match text.parse::<Table>() {
+            Ok(table) => table,
+            Err(err) => return (config, vec![err.message().to_string()]),
+        };
+
+        let mut warnings = vec![];
+        match table.remove("filetype") {
+            Some(Value::Table(filetypes)) => {
+                for (name, overrides) in filetypes {
+                    let Value::Table(overrides) = overrides else {
+                        warnings.push(format!("'filetype.{}' must be a table", name));
+                        continue;
+                    };
+                    // checked now so that the problems are reported on startup
+                    warnings.extend(
+                        Settings::default()
+                            .apply(&overrides)
+                            .into_iter()
+                            .map(|warning| format!("{} in 'filetype.{}'", warning, name)),
+                    );
+                    config.filetypes.insert(name, overrides);
+                }
+            }
+            Some(_) => warnings.push("'filetype' must be a table".to_string()),
+            None => (),
+        }
+        warnings.extend(config.settings.apply(&table));
+
+        (config, warnings)
+    }
+
+    /// Settings for a kind of file, with its overrides applied.
+    pub fn settings_for(&self, filetype: Option<&str>) -> Settings {
+        let mut settings = self.settings.clone();
+        if let Some(overrides) = filetype.and_then(|name| self.filetypes.get(name)) {
+            settings.apply(overrides);
+        }
+        settings
+    }
+}
@@ -0,0 +1,91 @@
+use crate::coords::Coordinates;
+
+/// Where an edit changed the text: what was between `start` and `old_end` is now
+/// between `start` and `new_end`. Used to move the other cursors along with the text
+/// when editing at several cursors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Change {
+    start: Coordinates<usize>,
+    old_end: Coordinates<usize>,
+    new_end: Coordinates<usize>,
+}
+
+impl Change {
+    pub fn new(
+        start: Coordinates<usize>,
+        old_end: Coordinates<usize>,
+        new_end: Coordinates<usize>,
+    ) -> Self {
+        Self {
+            start,
+            old_end,
+            new_end,
+        }
+    }
+
+    /// Where `pos` is after the change. Positions in the replaced text go to its end.
+    pub fn map(&self, pos: Coordinates<usize>) -> Coordinates<usize> {
+        let key = |pos: Coordinates<usize>| (pos.y(), pos.x());
+        if key(pos) < key(self.start) {
+            pos
+        } else if key(pos) < key(self.old_end) {
+            self.new_end
+        } else if pos.y() == self.old_end.y() {
+            Coordinates::new(
+                self.new_end.x() + pos.x() - self.old_end.x(),
+                self.new_end.y(),
+            )
+        } else {
+            Coordinates::new(pos.x(), pos.y() + self.new_end.y() - self.old_end.y())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: usize, y: usize) -> Coordinates<usize> {
+        Coordinates::new(x, y)
+    }
+
+    #[test]
+    fn insertion_on_a_row() {
+        // "ab" typed at (2, 1)
+        let change = Change::new(at(2, 1), at(2, 1), at(4, 1));
+        assert_eq!(change.map(at(1, 1)), at(1, 1));
+        assert_eq!(change.map(at(2, 1)), at(4, 1));
+        assert_eq!(change.map(at(5, 1)), at(7, 1));
+        assert_eq!(change.map(at(5, 2)), at(5, 2));
+        assert_eq!(change.map(at(5, 0)), at(5, 0));
+    }
+
+    #[test]
+    fn insertion_of_lines() {
+        // "x\ny\nz" typed at (3, 1)
+        let change = Change::new(at(3, 1), at(3, 1), at(1, 3));
+        assert_eq!(change.map(at(2, 1)), at(2, 1));
+        assert_eq!(change.map(at(6, 1)), at(4, 3));
+        assert_eq!(change.map(at(0, 2)), at(0, 4));
+    }
+
+    #[test]
+    fn deletion_across_rows() {
+        // from (2, 1) to (4, 3) deleted
+        let change = Change::new(at(2, 1), at(4, 3), at(2, 1));
+        assert_eq!(change.map(at(0, 0)), at(0, 0));
+        assert_eq!(change.map(at(7, 2)), at(2, 1));
+        assert_eq!(change.map(at(3, 3)), at(2, 1));
+        assert_eq!(change.map(at(6, 3)), at(4, 1));
+        assert_eq!(change.map(at(1, 5)), at(1, 3));
+    }
+
+    #[test]
+    fn replacement() {
+        // "abc" replaced with "d" at (0, 0)
+        let change = Change::new(at(0, 0), at(3, 0), at(1, 0));
+        assert_eq!(change.map(at(1, 0)), at(1, 0));
+        assert_eq!(change.map(at(3, 0)), at(1, 0));
+        assert_eq!(change.map(at(4, 0)), at(2, 0));
+    }
+}
@@ -1,30 +1,107 @@
 use errno::errno;
 use std::{
-    env, fs,
-    io::{self, Stdout},
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{self, IsTerminal, Read, Write},
     time::Duration,
     u16,
 };
 
 use crossterm::{
-    event::{self, poll, read, Event, KeyCode, KeyEvent, KeyModifiers},
+    event::{
+        self, poll, read, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
     terminal::{self, disable_raw_mode},
     QueueableCommand,
 };
 
 use super::*;
+use crate::block::{byte_range, pad_to, Block};
+use crate::brackets::{is_bracket, matching_bracket};
+use crate::cli::{Args, FileArg, Keymap};
+use crate::commands::Command;
+use crate::comment::CommentSyntax;
+use crate::config::{Config, Settings, OPTIONS};
 use crate::coords::Coordinates;
+use crate::cursors::Change;
+use crate::directions::Direction;
+use crate::editorconfig::{Charset, EditorConfig, LineEnding};
+use crate::emacs::{Emacs, EmacsCommand};
+use crate::ex::{self, ExCommand, Substitute};
+use crate::filetype::FileType;
+use crate::finder::FileIndex;
+use crate::goto::GoTo;
+use crate::highlight::highlight;
+use crate::indent::{self, closing_bracket, leading_whitespace, outdent, IndentStyle};
+use crate::keys::KeyBindings;
+use crate::prompt::{Prompt, PromptEvent};
+use crate::theme::Theme;
+use crate::vi::{
+    motion_target, text_object_range, InsertAt, Motion, Operator, Register, Target, Vi, ViCommand,
+};
+use crate::words::{step, word_boundary, CharClass};
 
 pub struct Editor {
     screen: Screen,
+    /// On the screen, the column is counted like `Screen`'s column offset: in
+    /// columns, a tab taking several of them
     cursor: Coordinates<u16>,
     rows: Vec<String>,
     file_name: String,
     has_changed: bool,
+    read_only: bool,
+    read_only_files: bool,
+    files: Vec<FileArg>,
+    current_file: usize,
+    stdin_contents: Option<String>,
+    pipe: bool,
+    vi: Option<Vi>,
+    register: Register,
+    emacs: Option<Emacs>,
+    yank_range: Option<(Coordinates<usize>, Coordinates<usize>)>,
+    selection_anchor: Option<Coordinates<usize>>,
+    /// Cursors besides the main one, in file coordinates
+    cursors: Vec<Coordinates<usize>>,
+    /// Edits made by the command running at one of the cursors, to move the others
+    changes: Option<Vec<Change>>,
+    block: Option<Block>,
+    /// Where the mouse button was pressed, as (column on the screen, row)
+    drag_from: Option<Coordinates<usize>>,
+    bindings: KeyBindings,
+    config: Config,
+    settings: Settings,
+    filetype: Option<&'static FileType>,
+    indent: IndentStyle,
+    /// Indentation guessed from the file, used when the settings don't give one
+    detected_indent: Option<IndentStyle>,
+    editorconfig: EditorConfig,
+    line_ending: LineEnding,
+    charset: Charset,
+    final_newline: bool,
+    search_term: Option<String>,
+    prompt_history: HashMap<PromptKind, Vec<String>>,
+    /// Problems found in the configuration on startup, shown one per key press
+    warnings: VecDeque<String>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum PromptKind {
+    Command,
+    FileName,
+    Search,
+    GoTo,
 }
 
+const STDIN_FILE: &str = "-";
+const STDIN_NAME: &str = "[stdin]";
+/// How often the file list is redrawn while the files are being listed
+const FILE_LIST_REFRESH: Duration = Duration::from_millis(50);
+/// Most copies pasted by a vi count, so that a mistyped one doesn't fill the memory
+const MAX_PASTE_COUNT: usize = 1000;
+
 impl Editor {
-    pub fn new() -> Self {
+    pub fn new(args: Args) -> Self {
         let (width, height) = match terminal::size() {
             Ok(size) => (size.0, size.1),
             Err(_) => {
@@ -33,20 +110,54 @@ impl Editor {
             }
         };
 
-        let stdout = match initialize_stdout() {
-            Ok(stdout) => stdout,
+        let output = match initialize_output() {
+            Ok(output) => output,
             Err(_) => {
-                eprintln!("Error initializing stdout: {}", errno());
+                eprintln!("Error initializing the terminal output: {}", errno());
                 std::process::exit(1);
             }
         };
 
         Self {
-            screen: Screen::new(stdout, width, height),
+            screen: Screen::new(output, width, height),
             cursor: Coordinates::default(),
             rows: vec!["".to_string()],
             file_name: "[New file]".to_string(),
             has_changed: false,
+            read_only: args.read_only,
+            read_only_files: args.read_only,
+            files: args.files,
+            current_file: 0,
+            stdin_contents: None,
+            pipe: args.pipe,
+            vi: match args.keymap {
+                Keymap::Vi => Some(Vi::new()),
+                _ => None,
+            },
+            register: Register::default(),
+            emacs: match args.keymap {
+                Keymap::Emacs => Some(Emacs::new()),
+                _ => None,
+            },
+            yank_range: None,
+            selection_anchor: None,
+            cursors: vec![],
+            changes: None,
+            block: None,
+            drag_from: None,
+            bindings: KeyBindings::default(),
+            config: Config::default(),
+            settings: Settings::default(),
+            filetype: None,
+            indent: IndentStyle::default(),
+            detected_indent: None,
+            editorconfig: EditorConfig::default(),
+            line_ending: LineEnding::default(),
+            charset: Charset::default(),
+            final_newline: false,
+            search_term: None,
+            prompt_history: HashMap::new(),
+            warnings: VecDeque::new(),
         }
     }
 
@@ -62,15 +173,7 @@ impl Editor {
         }
 
         loop {
-            match self.screen.refresh_screen(
-                &self.cursor,
-                &self.rows,
-                &self.file_name,
-                self.has_changed,
-            ) {
-                Ok(_) => (),
-                Err(_) => self.die("Error refreshing screen"),
-            }
+            self.refresh();
             match self.process_key_press() {
                 Ok(_) => (),
                 Err(err) => self.die(err),
@@ -79,38 +182,251 @@ impl Editor {
     }
 
     fn open(&mut self) {
-        match self
-            .screen
-            .set_status_msg("HELP: Ctrl-Q = quit | Ctrl-S = save")
+        let (bindings, key_warnings) = KeyBindings::load();
+        self.bindings = bindings;
+        let (config, config_warnings) = Config::load();
+        self.config = config;
+        let theme_warning = self.apply_settings().err();
+
+        self.warnings = (config_warnings.iter().chain(&theme_warning))
+            .map(|warning| format!("config.toml: {}", warning))
+            .chain(
+                key_warnings
+                    .iter()
+                    .map(|warning| format!("keys.toml: {}", warning)),
+            )
+            .collect();
+        if self.warnings.is_empty() {
+            let msg = self.help_msg();
+            match self.screen.set_status_msg(msg) {
+                Ok(_) => (),
+                Err(_) => self.die("Error in status msg"),
+            }
+        } else if self.show_next_warning().is_err() {
+            self.die("Error in status msg");
+        }
+        if !self.files.is_empty() {
+            self.open_file_arg(0);
+        }
+    }
+
+    /// Shows the first of the startup warnings left, saying how many follow.
+    fn show_next_warning(&mut self) -> io::Result<()> {
+        let Some(warning) = self.warnings.pop_front() else {
+            return Ok(());
+        };
+        let msg = match self.warnings.len() {
+            0 => warning,
+            left => format!("{} ({} more, press a key)", warning, left),
+        };
+        self.screen.set_status_msg(msg)
+    }
+
+    fn help_msg(&self) -> String {
+        let help: Vec<String> = [
+            (Command::Quit, "quit"),
+            (Command::Save, "save"),
+            (Command::Open, "open"),
+            (Command::GoTo, "go to line"),
+        ]
+        .into_iter()
+        .filter_map(|(command, description)| {
+            let key = self.bindings.key_for(command)?;
+            Some(format!("{} = {}", key, description))
+        })
+        .collect();
+        format!("HELP: {}", help.join(" | "))
+    }
+
+    fn open_file_arg(&mut self, index: usize) -> bool {
+        let file = self.files[index].clone();
+        if !self.load_file(file.path) {
+            return false;
+        }
+        self.current_file = index;
+
+        if let Some(position) = file.position {
+            match position.resolve(0, &self.rows) {
+                Ok(coord) => self.go_to_coordinate(coord),
+                Err(err) => match self.screen.set_status_msg(err) {
+                    Ok(_) => (),
+                    Err(_) => self.die("Error in status msg"),
+                },
+            }
+        }
+        true
+    }
+
+    fn switch_file(&mut self, forward: bool) -> Result<(), IoError> {
+        if self.files.len() < 2 {
+            return Ok(());
+        }
+        if self.has_changed
+            && !self.confirm("WARNING, file not saved. Do you really want to switch? [y/n]")?
         {
+            return Ok(());
+        }
+
+        let index = if forward {
+            (self.current_file + 1) % self.files.len()
+        } else {
+            (self.current_file + self.files.len() - 1) % self.files.len()
+        };
+        if !self.open_file_arg(index) {
+            return Ok(());
+        }
+
+        match self.screen.set_status_msg(format!(
+            "{} ({}/{})",
+            self.file_name,
+            index + 1,
+            self.files.len()
+        )) {
             Ok(_) => (),
             Err(_) => self.die("Error in status msg"),
         }
-        match env::args().nth(1) {
-            Some(file) => match fs::read_to_string(&file) {
-                Ok(contents) => {
-                    self.file_name = file;
+        Ok(())
+    }
 
-                    let lines: Vec<String> =
-                        contents.lines().map(|line| line.to_string()).collect();
-                    self.rows = lines;
-                }
-                Err(_) => {
-                    self.file_name = file;
-                }
-            },
-            None => return,
+    /// Opens `file`, as an empty buffer when it doesn't exist yet. When it can't be
+    /// read the error is shown and the current buffer is kept, so that saving doesn't
+    /// overwrite the file.
+    fn load_file(&mut self, file: String) -> bool {
+        let editorconfig = match file == STDIN_FILE {
+            true => EditorConfig::default(),
+            false => EditorConfig::for_file(&file),
+        };
+        let contents = match file == STDIN_FILE {
+            true => self
+                .read_stdin()
+                .map(|contents| (contents, Charset::default())),
+            false => self.read_file(&file, &editorconfig),
+        };
+        let (contents, charset) = match contents {
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                (String::new(), editorconfig.charset().unwrap_or_default())
+            }
+            Err(err) => {
+                self.set_status_msg(format!("Can't read {}: {}", file, err));
+                return false;
+            }
+        };
+        self.editorconfig = editorconfig;
+        self.charset = charset;
+        self.line_ending = LineEnding::detect(&contents);
+        self.final_newline = contents.ends_with(['\n', '\r']);
+        let contents = match self.line_ending {
+            LineEnding::Cr => contents.replace('\r', "\n"),
+            _ => contents,
+        };
+        self.rows = contents.lines().map(|line| line.to_string()).collect();
+        if self.rows.is_empty() {
+            self.rows.push("".to_string());
+        }
+        self.detected_indent = indent::detect(&self.rows);
+        self.cursors.clear();
+        self.block = None;
+
+        self.read_only = self.read_only_files || !is_writable(&file);
+        self.file_name = if file == STDIN_FILE {
+            STDIN_NAME.to_string()
+        } else {
+            file
         };
+        self.has_changed = false;
+        self.cursor = Coordinates::default();
+        self.selection_anchor = None;
+        self.screen.reset_row_offset();
+        self.screen.reset_column_offset();
+        if let Err(err) = self.apply_settings() {
+            self.set_status_msg(err);
+        }
+        true
+    }
+
+    /// Reads `file` in the charset of its byte order mark, or the one set in
+    /// `.editorconfig`, and returns that charset too.
+    fn read_file(&self, file: &str, editorconfig: &EditorConfig) -> io::Result<(String, Charset)> {
+        let bytes = fs::read(file)?;
+        let charset = Charset::from_bom(&bytes)
+            .or(editorconfig.charset())
+            .unwrap_or_default();
+        let contents = charset
+            .decode(&bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok((contents, charset))
+    }
+
+    /// Takes the settings of the current file's type from the configuration.
+    fn apply_settings(&mut self) -> Result<(), String> {
+        self.filetype = FileType::detect(&self.file_name);
+        self.settings = self
+            .config
+            .settings_for(self.filetype.map(|filetype| filetype.name));
+        self.editorconfig
+            .apply(&mut self.settings, self.detected_indent);
+        if let Some(line_ending) = self.editorconfig.end_of_line() {
+            self.line_ending = line_ending;
+        }
+        if let Some(charset) = self.editorconfig.charset() {
+            self.charset = charset;
+        }
+        if let Some(final_newline) = self.editorconfig.insert_final_newline() {
+            self.final_newline = final_newline;
+        }
+        self.update_indent();
+        self.update_screen()
+    }
+
+    fn update_indent(&mut self) {
+        self.indent = self
+            .settings
+            .indent
+            .or(self.detected_indent)
+            .unwrap_or_default();
+    }
+
+    fn update_screen(&mut self) -> Result<(), String> {
+        self.screen
+            .apply_settings(&self.settings)
+            .map_err(|err| err.to_string())?;
+        if self.screen.theme_name() != self.settings.theme {
+            self.screen.set_theme(Theme::load(&self.settings.theme)?);
+        }
+        Ok(())
+    }
+
+    fn read_stdin(&mut self) -> io::Result<String> {
+        if self.stdin_contents.is_none() {
+            let mut contents = String::new();
+            io::stdin().read_to_string(&mut contents)?;
+            self.stdin_contents = Some(contents);
+        }
+        Ok(self.stdin_contents.clone().unwrap_or_default())
     }
 
     pub fn read_key(&mut self) -> Result<Option<KeyEvent>, IoError> {
+        match self.read_event()? {
+            Some(Event::Key(key_event)) => Ok(Some(key_event)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn read_event(&mut self) -> Result<Option<Event>, IoError> {
         loop {
             match poll(Duration::from_secs(0)) {
                 Ok(is_event) => {
                     if is_event {
                         match read() {
                             Ok(Event::Key(key_event)) => {
-                                return Ok(Some(key_event));
+                                return Ok(Some(Event::Key(key_event)));
+                            }
+                            Ok(Event::Paste(text)) => {
+                                return Ok(Some(Event::Paste(text)));
+                            }
+                            Ok(Event::Mouse(mouse)) => {
+                                return Ok(Some(Event::Mouse(mouse)));
                             }
                             Ok(_) => {
                                 return Ok(None);
@@ -125,426 +441,1628 @@ impl Editor {
     }
 
     pub fn process_key_press(&mut self) -> Result<(), IoError> {
-        Ok(match self.read_key()? {
-            Some(c) => match c.code {
-                KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {
-                    self.move_cursor(c.code)
-                }
-                KeyCode::Char(ch) => {
-                    if ch == 'q' && c.modifiers.contains(KeyModifiers::CONTROL) {
-                        if self.has_changed {
-                            match self.screen.set_status_msg(
-                                "WARNING, files not saved. Do you really want to quit? [y/n]",
-                            ) {
-                                Ok(_) => (),
-                                Err(_) => self.die("Error in status msg"),
-                            }
-                            match self
-                                .read_key()?
-                                .unwrap_or(KeyEvent::new(KeyCode::End, KeyModifiers::CONTROL))
-                                .code
-                            {
-                                KeyCode::Char('y') => self.exit(),
-                                _ => (),
-                            }
-                        } else {
-                            self.exit()
-                        }
-                    } else if ch == 's' && c.modifiers.contains(KeyModifiers::CONTROL) {
-                        self.save_file();
-                    } else if ch == 'f' && c.modifiers.contains(KeyModifiers::CONTROL) {
-                        match self.prompt_search() {
-                            Ok(_) => (),
-                            Err(err) => self.die(err),
-                        }
-                    } else {
-                        if ch.is_ascii() {
-                            self.insert_char(ch);
-                        }
-                    }
+        let event = self.read_event()?;
+        let is_key = matches!(event, Some(Event::Key(_)));
+        self.process_event(event)?;
+        if is_key && !self.warnings.is_empty() {
+            self.show_next_warning()
+                .map_err(|_| IoError::new("Error in status msg"))?;
+        }
+        Ok(())
+    }
+
+    fn process_event(&mut self, event: Option<Event>) -> Result<(), IoError> {
+        let c = match event {
+            Some(Event::Key(key_event)) => key_event,
+            Some(Event::Paste(text)) if self.block.is_some() => {
+                let text = text.replace('\r', "");
+                let text = text.strip_suffix('\n').unwrap_or(&text);
+                let lines: Vec<&str> = text.split('\n').collect();
+                match lines.as_slice() {
+                    [line] => self.type_in_block(line),
+                    lines => self.paste_block(lines),
                 }
+                return Ok(());
+            }
+            Some(Event::Paste(text)) => {
+                return self.for_each_cursor(|editor| {
+                    editor.paste(&text);
+                    Ok(())
+                });
+            }
+            Some(Event::Mouse(mouse)) => {
+                self.process_mouse(mouse);
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
 
-                KeyCode::Enter => self.insert_enter(),
-                KeyCode::Backspace => self.process_backspace(),
-                KeyCode::Delete => self.process_delete(),
-                _ => (),
-            },
-            None => (),
-        })
+        if let Some(vi) = self.vi.as_mut() {
+            return match vi.handle_key(c) {
+                Some(command) => self.execute_vi(command),
+                None => Ok(()),
+            };
+        }
+        if let Some(emacs) = self.emacs.as_mut() {
+            return match emacs.handle_key(c) {
+                Some(command) => self.execute_emacs(command),
+                None => Ok(()),
+            };
+        }
+        self.process_key(c)
     }
 
-    fn move_cursor(&mut self, code: KeyCode) {
-        match code {
-            KeyCode::Up => match self.cursor.try_bounded_up_by(1, ..self.screen.height) {
-                Some(coord) => {
-                    let eol_cursor =
-                        self.cursor_end_of_line(coord.y() + self.screen.get_row_offset());
+    fn process_key(&mut self, c: KeyEvent) -> Result<(), IoError> {
+        if let Some(command) = self.bindings.get(c) {
+            return self.run_command(command);
+        }
+        if let KeyCode::Char(ch) = c.code {
+            if ch.is_ascii()
+                && !c
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+            {
+                if self.block.is_some() {
+                    self.type_in_block(&ch.to_string());
+                } else {
+                    self.for_each_cursor(|editor| {
+                        editor.insert_char(ch);
+                        Ok(())
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
 
-                    let x = eol_cursor.x().min(coord.x());
-                    let y = coord.y();
+    fn run_command(&mut self, command: Command) -> Result<(), IoError> {
+        if !command.keeps_block() {
+            self.block = None;
+        }
+        if !self.cursors.is_empty() {
+            if command.at_each_cursor() {
+                return self.for_each_cursor(|editor| editor.execute_command(command));
+            }
+            if !command.keeps_cursors() {
+                self.cursors.clear();
+            }
+        }
+        self.execute_command(command)
+    }
 
-                    self.cursor = Coordinates::new(x, y);
+    fn execute_command(&mut self, command: Command) -> Result<(), IoError> {
+        match command {
+            Command::Quit => self.quit()?,
+            Command::Save => self.save_file(),
+            Command::Find => self.prompt_search()?,
+            Command::Open => self.prompt_open_file()?,
+            Command::GoTo => self.prompt_go_to()?,
+            Command::ToggleReadOnly => self.toggle_read_only(),
+            Command::NextFile => self.switch_file(true)?,
+            Command::PreviousFile => self.switch_file(false)?,
+            Command::MoveLeft => self.move_cursor(KeyCode::Left),
+            Command::MoveRight => self.move_cursor(KeyCode::Right),
+            Command::MoveUp => self.move_cursor(KeyCode::Up),
+            Command::MoveDown => self.move_cursor(KeyCode::Down),
+            Command::MoveWordLeft => self.move_word(Direction::Left),
+            Command::MoveWordRight => self.move_word(Direction::Right),
+            Command::MoveLineStart => self.move_home(),
+            Command::MoveLineEnd => self.move_end(),
+            Command::MovePageUp => self.move_page(false),
+            Command::MovePageDown => self.move_page(true),
+            Command::MoveFileStart => self.move_to_document_start(),
+            Command::MoveFileEnd => self.move_to_document_end(),
+            Command::MatchingBracket => self.jump_to_matching_bracket(),
+            Command::Newline => self.insert_enter(),
+            Command::Backspace if self.block.is_some() => self.delete_in_block(Direction::Left),
+            Command::Backspace => self.process_backspace(),
+            Command::Delete if self.block.is_some() => self.delete_in_block(Direction::Right),
+            Command::Delete => self.process_delete(),
+            Command::DeleteWordLeft => self.delete_word(Direction::Left),
+            Command::DeleteWordRight => self.delete_word(Direction::Right),
+            Command::ToggleComment => self.toggle_comment(),
+            Command::MoveLinesUp => self.move_lines(false),
+            Command::MoveLinesDown => self.move_lines(true),
+            Command::DuplicateLines => self.duplicate_lines(),
+            Command::DeleteLines => self.delete_lines(),
+            Command::JoinLines => self.join_lines(),
+            Command::Indent => self.indent(),
+            Command::Outdent => self.outdent_lines(),
+            Command::TrimWhitespace => {
+                if self.check_editable() {
+                    self.trim_whitespace();
+                }
+            }
+            Command::AddCursorAtNext => self.add_cursor_at_next(),
+            Command::AddCursorAbove => self.add_cursor_vertically(false),
+            Command::AddCursorBelow => self.add_cursor_vertically(true),
+            Command::ClearCursors => self.cursors.clear(),
+            Command::BlockLeft => self.extend_block(Direction::Left),
+            Command::BlockRight => self.extend_block(Direction::Right),
+            Command::BlockUp => self.extend_block(Direction::Up),
+            Command::BlockDown => self.extend_block(Direction::Down),
+            Command::Copy => self.copy(false),
+            Command::Cut => self.copy(true),
+            Command::Paste => self.paste_register(),
+            Command::Prompt => self.prompt_command()?,
+        }
+        Ok(())
+    }
 
-                    if self.cursor.x() as usize >= self.get_row(self.cursor.y()).len() {
-                        self.screen.reset_column_offset();
-                    }
+    /// Runs `run` at every cursor, from the first one in the file to the last, moving
+    /// the other cursors along with the text each run changes.
+    fn for_each_cursor(
+        &mut self,
+        mut run: impl FnMut(&mut Self) -> Result<(), IoError>,
+    ) -> Result<(), IoError> {
+        if self.cursors.is_empty() {
+            return run(self);
+        }
+        let row_offset = self.screen.get_row_offset();
+        let col_offset = self.screen.get_col_offset();
+
+        // the main cursor is the last one of the list
+        let mut cursors = std::mem::take(&mut self.cursors);
+        cursors.push(self.cursor_position());
+        let main = cursors.len() - 1;
+        let mut order: Vec<usize> = (0..cursors.len()).collect();
+        order.sort_by_key(|&i| (cursors[i].y(), cursors[i].x()));
+        // the selection only applies at the main cursor
+        let mut anchor = self.selection_anchor.take();
+
+        for i in order {
+            let at = cursors[i];
+            self.move_to(at);
+            if i == main {
+                self.selection_anchor = anchor.take();
+            }
+            self.changes = Some(vec![]);
+            let result = run(self);
+            for change in self.changes.take().unwrap_or_default() {
+                for cursor in cursors.iter_mut().chain(&mut anchor) {
+                    *cursor = change.map(*cursor);
                 }
-                None => self.screen.scroll_up(1),
-            },
-            KeyCode::Down => match self.cursor.try_bounded_down_by(1, ..self.screen.height) {
-                Some(coord) => {
-                    if self.rows.is_empty()
-                        || (((self.cursor.y() + self.screen.get_row_offset()) as usize)
-                            .saturating_add(1)
-                            == self.rows.len())
-                    {
-                        return;
-                    }
-                    let eol_cursor =
-                        self.cursor_end_of_line(coord.y() + self.screen.get_row_offset());
-                    let x = eol_cursor.x().min(coord.x());
-                    let y: u16 = (self.rows.len().saturating_sub(1).min(coord.y() as usize))
-                        .try_into()
-                        .unwrap();
+            }
+            result?;
+            cursors[i] = self.cursor_position();
+            if i == main {
+                anchor = self.selection_anchor.take();
+            }
+        }
+        self.selection_anchor = anchor;
 
-                    self.cursor = Coordinates::new(x, y);
+        self.screen.reset_row_offset();
+        self.screen.scroll_down(row_offset);
+        self.screen.reset_column_offset();
+        self.screen.scroll_right(col_offset);
+        self.move_to(cursors[main]);
+        cursors.truncate(main);
+        self.set_cursors(cursors);
+        Ok(())
+    }
 
-                    if self.cursor.x() as usize >= self.get_row(self.cursor.y()).len() {
-                        self.screen.reset_column_offset();
-                    }
-                }
-                None => {
-                    if ((self.cursor.y() + self.screen.get_row_offset()) as usize).saturating_add(1)
-                        < self.rows.len()
-                    {
-                        self.screen.scroll_down(1)
-                    }
-                }
-            },
-            KeyCode::Left => match self.cursor.try_bounded_left_by(1, ..self.screen.width) {
-                Some(coord) => {
-                    let eol_cursor =
-                        self.cursor_end_of_line(coord.y() + self.screen.get_row_offset());
-                    let x = eol_cursor.x().min(coord.x());
-                    let y: u16 = coord.y();
-
-                    self.cursor = Coordinates::new(x, y);
-                }
-                None => {
-                    if self.cursor == Coordinates::new(0, 0) {
-                        if self.screen.get_row_offset() == 0 && self.screen.get_col_offset() == 0 {
-                            // beguinning of file
-                            return;
-                        } else if self.screen.get_col_offset() != 0 {
-                            self.screen.scroll_left(1);
-                        } else {
-                            let prev_eol_cursor = self.cursor_end_of_line(
-                                self.cursor.y() + self.screen.get_row_offset().saturating_sub(1),
-                            );
-                            let x = prev_eol_cursor.x();
-                            let y: u16 = 0;
-                            self.cursor = Coordinates::new(x, y);
-                            self.screen.scroll_up(1);
-                        }
-                    } else if self.cursor.x() == 0 && self.screen.get_col_offset() != 0 {
-                        self.screen.scroll_left(1);
-                    } else if self.cursor.x() == 0 && self.screen.get_col_offset() == 0 {
-                        // line beguinning
-                        let prev_eol_cursor = self.cursor_end_of_line(
-                            self.cursor.y().saturating_sub(1) + self.screen.get_row_offset(),
-                        );
-
-                        let mut x = prev_eol_cursor.x();
-                        let y: u16 = prev_eol_cursor.y() - self.screen.get_row_offset();
-
-                        if x > self.screen.width {
-                            self.screen.scroll_right(x - self.screen.width + 1);
-                            x = self.screen.width - 1;
-                        }
+    /// Notes an edit for `for_each_cursor`.
+    fn record(&mut self, change: Change) {
+        if let Some(changes) = &mut self.changes {
+            changes.push(change);
+        }
+    }
 
-                        self.cursor = Coordinates::new(x, y);
-                    }
+    /// Sets the other cursors, without the ones on the main cursor or on each other.
+    /// Cursors past the end of the text are brought back into it.
+    fn set_cursors(&mut self, cursors: Vec<Coordinates<usize>>) {
+        let position = self.cursor_position();
+        let mut cursors: Vec<Coordinates<usize>> = cursors
+            .into_iter()
+            .map(|cursor| {
+                let y = cursor.y().min(self.rows.len() - 1);
+                let row = &self.rows[y];
+                let mut x = cursor.x().min(row.len());
+                while !row.is_char_boundary(x) {
+                    x -= 1;
                 }
-            },
-            KeyCode::Right => match self.cursor.try_bounded_right_by(1, ..self.screen.width) {
-                Some(coord) => {
-                    if self.rows.is_empty() {
-                        return;
-                    }
-                    let eol_cursor =
-                        self.cursor_end_of_line(coord.y() + self.screen.get_row_offset());
-                    let x;
-                    let y;
-
-                    if coord.x() > eol_cursor.x() {
-                        // end of the line
-                        if ((coord.y() + self.screen.get_row_offset()) as usize)
-                            >= self.rows.len().saturating_sub(1)
-                        {
-                            // end of file
-                            x = self.cursor.x();
-                            y = self.cursor.y();
-                        } else if coord.y() == self.screen.height - 1 {
-                            // end of screen
-                            self.screen.scroll_down(1);
-                            y = coord.y();
-                            x = 0;
-                        } else {
-                            y = coord.y().saturating_add(1);
-                            x = 0;
-                        }
-                    } else {
-                        x = eol_cursor.x().min(coord.x());
-                        y = coord.y();
-                    }
+                Coordinates::new(x, y)
+            })
+            .collect();
+        cursors.sort_by_key(|cursor| (cursor.y(), cursor.x()));
+        cursors.dedup();
+        cursors.retain(|&cursor| cursor != position);
+        self.cursors = cursors;
+    }
 
-                    self.cursor = Coordinates::new(x, y);
+    /// Adds a cursor on the next occurrence of the selected text, or of the word under
+    /// the cursor, after the last cursor added.
+    fn add_cursor_at_next(&mut self) {
+        let position = self.cursor_position();
+        let (start, end, whole_word) = match self.selection_range() {
+            Some((start, end)) if start.y() == end.y() && start != end => (start, end, false),
+            Some(_) => {
+                self.set_status_msg("Only text on a single line can be searched");
+                return;
+            }
+            None => {
+                let row = &self.rows[position.y()];
+                let is_word = |ch: char| CharClass::of(ch) == CharClass::Word;
+                let start = row[..position.x()]
+                    .char_indices()
+                    .rev()
+                    .take_while(|&(_, ch)| is_word(ch))
+                    .last()
+                    .map_or(position.x(), |(x, _)| x);
+                let end = row[position.x()..]
+                    .char_indices()
+                    .find(|&(_, ch)| !is_word(ch))
+                    .map_or(row.len(), |(x, _)| position.x() + x);
+                if start == end {
+                    self.set_status_msg("No word under the cursor");
+                    return;
                 }
-                None => {
-                    if ((self.cursor.x() + self.screen.get_col_offset()) as usize)
-                        < self.get_row(self.cursor.y()).len()
-                    {
-                        self.screen.scroll_right(1);
-                    } else {
-                        let new_y: u16 = self.cursor.y().saturating_add(1);
-                        self.screen.reset_column_offset();
-                        self.cursor = Coordinates::new(0, new_y);
-                    }
+                (
+                    Coordinates::new(start, position.y()),
+                    Coordinates::new(end, position.y()),
+                    true,
+                )
+            }
+        };
+        let term = self.rows[start.y()][start.x()..end.x()].to_string();
+        let offset = position.x().clamp(start.x(), end.x()) - start.x();
+
+        // from the end of the occurrence of the last cursor added, going round the file
+        let last = self.cursors.last().copied().unwrap_or(position);
+        let from_x = (last.x().saturating_sub(offset) + term.len()).min(self.rows[last.y()].len());
+        let rows = self.rows.len();
+        for i in 0..=rows {
+            let y = (last.y() + i) % rows;
+            let row = &self.rows[y];
+            for (x, _) in row.match_indices(term.as_str()) {
+                if (i == 0 && x < from_x) || (i == rows && x >= from_x) {
+                    continue;
                 }
-            },
-            _ => (),
+                let is_word =
+                    |ch: Option<char>| ch.is_some_and(|ch| CharClass::of(ch) == CharClass::Word);
+                if whole_word
+                    && (is_word(row[..x].chars().next_back())
+                        || is_word(row[x + term.len()..].chars().next()))
+                {
+                    continue;
+                }
+                let cursor = Coordinates::new(x + offset, y);
+                if cursor != position && !self.cursors.contains(&cursor) {
+                    self.cursors.push(cursor);
+                    return;
+                }
+            }
         }
+        self.set_status_msg(format!("No more occurrences of '{}'", term));
     }
 
-    fn cursor_end_of_line(&mut self, y: u16) -> Coordinates<u16> {
-        let true_y: u16 = self
-            .rows
-            .len()
-            .saturating_sub(1)
-            .min(y as usize)
-            .try_into()
-            .unwrap();
-        let true_x: u16 = self.get_row(true_y).len().try_into().unwrap();
-
-        Coordinates::new(true_x, true_y)
-    }
-
-    fn insert_char(&mut self, ch: char) {
-        self.has_changed = true;
-        let current_row_index = self.cursor.y() + self.screen.get_row_offset();
-        let current_col_index = self.cursor.x() + self.screen.get_col_offset();
-
-        let mut row: String = self
-            .rows
+    /// Adds a cursor on the line above the first cursor, or below the last one, at
+    /// the column of the main cursor.
+    fn add_cursor_vertically(&mut self, down: bool) {
+        let position = self.cursor_position();
+        let edge = self
+            .cursors
             .iter()
-            .nth(current_row_index as usize)
-            .unwrap()
-            .clone();
-        row.insert(current_col_index as usize, ch);
+            .chain([&position])
+            .map(|cursor| cursor.y())
+            .reduce(if down { usize::max } else { usize::min })
+            .unwrap_or(position.y());
+        let y = match down {
+            true if edge + 1 < self.rows.len() => edge + 1,
+            false if edge > 0 => edge - 1,
+            _ => return,
+        };
+        self.cursors.push(self.same_column(position, y));
+    }
 
-        self.rows.remove(current_row_index as usize);
-        self.rows.insert(current_row_index as usize, row);
+    /// A click moves the cursor, with Alt or Ctrl it adds a cursor or removes the one
+    /// clicked. Dragging with Alt selects a block.
+    fn process_mouse(&mut self, mouse: MouseEvent) {
+        let dragged = mouse.kind == MouseEventKind::Drag(MouseButton::Left);
+        if !(dragged || mouse.kind == MouseEventKind::Down(MouseButton::Left))
+            || mouse.row >= self.screen.height
+        {
+            return;
+        }
+        let y = (mouse.row + self.screen.get_row_offset()) as usize;
+        let y = y.min(self.rows.len() - 1);
+        let col =
+            mouse.column.saturating_sub(self.screen.get_gutter()) + self.screen.get_col_offset();
+        let col = col as usize;
+
+        if dragged {
+            // blocks are edited with the default key bindings only
+            let alt = mouse.modifiers.contains(KeyModifiers::ALT);
+            if let (true, Some(from), None, None) = (alt, self.drag_from, &self.vi, &self.emacs) {
+                self.cursors.clear();
+                self.set_block(Block {
+                    anchor: from,
+                    corner: Coordinates::new(col, y),
+                });
+            }
+            return;
+        }
+        self.drag_from = Some(Coordinates::new(col, y));
+        self.block = None;
+        let x = byte_at_col(&self.rows[y], col, self.settings.tab_width);
+        let target = Coordinates::new(x, y);
+
+        if mouse
+            .modifiers
+            .intersects(KeyModifiers::ALT | KeyModifiers::CONTROL)
+        {
+            let position = self.cursor_position();
+            if let Some(index) = self.cursors.iter().position(|&cursor| cursor == target) {
+                self.cursors.remove(index);
+            } else if target != position {
+                self.cursors.push(target);
+            }
+        } else {
+            self.cursors.clear();
+            self.selection_anchor = None;
+            self.move_to(target);
+        }
+    }
 
-        self.move_cursor(KeyCode::Right);
+    /// Starts a block at the cursor, or moves the corner of the block one column or
+    /// row. The corner can go past the end of the rows.
+    fn extend_block(&mut self, direction: Direction) {
+        let block = self.block.unwrap_or_else(|| {
+            let position = self.cursor_position();
+            let col = display_col(
+                &self.rows[position.y()],
+                position.x(),
+                self.settings.tab_width,
+            );
+            Block::new(Coordinates::new(col, position.y()))
+        });
+        let corner = block.corner;
+        let corner = match direction {
+            Direction::Left => Coordinates::new(corner.x().saturating_sub(1), corner.y()),
+            Direction::Right => Coordinates::new(corner.x() + 1, corner.y()),
+            Direction::Up => Coordinates::new(corner.x(), corner.y().saturating_sub(1)),
+            Direction::Down => {
+                Coordinates::new(corner.x(), (corner.y() + 1).min(self.rows.len() - 1))
+            }
+        };
+        self.set_block(Block {
+            anchor: block.anchor,
+            corner,
+        });
     }
 
-    fn insert_enter(&mut self) {
-        self.has_changed = true;
-        let current_row_index = self.cursor.y() + self.screen.get_row_offset();
-        let current_col_index = self.cursor.x() + self.screen.get_col_offset();
+    /// Sets the block and moves the cursor to its corner, or to the end of the row
+    /// when the corner is past it.
+    fn set_block(&mut self, block: Block) {
+        let corner = block.corner;
+        let x = byte_at_col(&self.rows[corner.y()], corner.x(), self.settings.tab_width);
+        self.move_to(Coordinates::new(x, corner.y()));
+        self.block = Some(block);
+    }
 
-        let mut row: String = self
-            .rows
-            .iter()
-            .nth(current_row_index as usize)
-            .unwrap()
-            .clone();
-        let post_cursor_row = &row.clone()[(current_col_index as usize)..];
-
-        row.truncate(current_col_index as usize);
-
-        self.rows.remove(current_row_index as usize);
-        self.rows.insert(current_row_index as usize, row);
-        self.rows.insert(
-            current_row_index.saturating_add(1) as usize,
-            post_cursor_row.to_string(),
-        );
+    /// Text of the block in each of its rows.
+    fn block_text(&self, block: &Block) -> Vec<String> {
+        let columns = block.columns();
+        block
+            .rows()
+            .map(|y| {
+                let row = &self.rows[y];
+                row[byte_range(row, &columns, self.settings.tab_width)].to_string()
+            })
+            .collect()
+    }
 
-        self.move_cursor(KeyCode::Down);
-        self.cursor = Coordinates::new(0, self.cursor.y());
-        self.screen.reset_column_offset();
+    /// Deletes the text of the block from its rows and returns it.
+    fn delete_block(&mut self, block: &Block) -> Vec<String> {
+        let columns = block.columns();
+        let tab_width = self.settings.tab_width;
+        let text: Vec<String> = block
+            .rows()
+            .map(|y| {
+                let range = byte_range(&self.rows[y], &columns, tab_width);
+                self.rows[y].drain(range).collect()
+            })
+            .collect();
+        if text.iter().any(|text| !text.is_empty()) {
+            self.has_changed = true;
+        }
+        text
     }
 
-    fn process_backspace(&mut self) {
+    /// Types `text` in every row of the block, in place of what it selects. Short
+    /// rows are padded with spaces up to the block.
+    fn type_in_block(&mut self, text: &str) {
+        let Some(block) = self.block else {
+            return;
+        };
+        if !self.check_editable() {
+            return;
+        }
+        self.delete_block(&block);
+        let tab_width = self.settings.tab_width;
+        let col = block.columns().start;
+        let mut end = col;
+        for y in block.rows() {
+            let row = &mut self.rows[y];
+            let x = pad_to(row, col, tab_width);
+            row.insert_str(x, text);
+            end = display_col(row, x + text.len(), tab_width);
+        }
         self.has_changed = true;
-        let current_row_index = self.cursor.y() + self.screen.get_row_offset();
-        let current_col_index = self.cursor.x() + self.screen.get_col_offset();
-
-        let mut row: String = self.get_row(current_row_index);
+        self.set_block(block.collapsed(end));
+    }
 
-        self.move_cursor(KeyCode::Left);
+    /// Backspace and Delete in a block delete what it selects, or the column before
+    /// it or under it when it has no columns.
+    fn delete_in_block(&mut self, direction: Direction) {
+        let Some(block) = self.block else {
+            return;
+        };
+        if !self.check_editable() {
+            return;
+        }
+        let col = block.columns().start;
+        let block = match direction {
+            _ if !block.columns().is_empty() => block,
+            Direction::Left if col > 0 => Block {
+                anchor: Coordinates::new(col - 1, block.anchor.y()),
+                corner: Coordinates::new(col, block.corner.y()),
+            },
+            Direction::Left => return,
+            _ => Block {
+                anchor: Coordinates::new(col, block.anchor.y()),
+                corner: Coordinates::new(col + 1, block.corner.y()),
+            },
+        };
+        self.delete_block(&block);
+        self.set_block(block.collapsed(block.columns().start));
+    }
 
-        if current_col_index == 0 && current_row_index == 0 {
+    /// Copies the block, or else the selected text, and deletes it when cutting.
+    fn copy(&mut self, cut: bool) {
+        if cut && !self.check_editable() {
             return;
-        } else if current_col_index == 0 {
-            let mut prev_row: String = self.get_row(current_row_index.saturating_sub(1));
-            prev_row += &row;
+        }
+        match self.block {
+            Some(block) => {
+                let text = match cut {
+                    true => self.delete_block(&block),
+                    false => self.block_text(&block),
+                };
+                self.register = Register {
+                    text: text.join("\n"),
+                    linewise: false,
+                    block: true,
+                };
+                if cut {
+                    self.set_block(block.collapsed(block.columns().start));
+                }
+            }
+            None => {
+                let Some((start, end)) = self.selection_range().filter(|(start, end)| start != end)
+                else {
+                    self.set_status_msg("Nothing selected");
+                    return;
+                };
+                let text = match cut {
+                    true => {
+                        self.selection_anchor = None;
+                        self.delete_range(start, end)
+                    }
+                    false => self.text_range(start, end),
+                };
+                self.register = Register {
+                    text,
+                    linewise: false,
+                    block: false,
+                };
+            }
+        }
+    }
 
-            self.rows.remove(current_row_index as usize);
-            self.rows
-                .remove(current_row_index.saturating_sub(1) as usize);
-            self.rows
-                .insert(current_row_index.saturating_sub(1) as usize, prev_row);
+    /// Pastes what was copied: a block goes in the same columns of the rows from the
+    /// cursor down, lines yanked in vi go above the current one and text at the cursor.
+    fn paste_register(&mut self) {
+        if !self.check_editable() {
+            return;
+        }
+        let register = self.register.clone();
+        if register.block {
+            let lines: Vec<&str> = register.text.split('\n').collect();
+            self.paste_block(&lines);
+        } else if register.linewise {
+            let position = self.cursor_position();
+            let lines: Vec<String> = register.text.split('\n').map(String::from).collect();
+            let count = lines.len();
+            self.rows.splice(position.y()..position.y(), lines);
+            let start = Coordinates::new(0, position.y());
+            self.record(Change::new(
+                start,
+                start,
+                Coordinates::new(0, position.y() + count),
+            ));
+            self.has_changed = true;
+            self.move_to(Coordinates::new(position.x(), position.y() + count));
         } else {
-            row.remove(current_col_index.saturating_sub(1) as usize);
-            self.rows.remove(current_row_index as usize);
-            self.rows.insert(current_row_index as usize, row);
+            self.paste(&register.text);
         }
     }
 
-    fn process_delete(&mut self) {
+    /// Pastes `lines` one below the other, starting at the same column, in place of
+    /// the block or at the cursor. Rows are added at the end of the file if needed.
+    fn paste_block(&mut self, lines: &[&str]) {
+        if !self.check_editable() {
+            return;
+        }
+        // a final newline doesn't add a row
+        let lines = lines.strip_suffix(&[""]).unwrap_or(lines);
+        let tab_width = self.settings.tab_width;
+        let (top, col) = match self.block.take() {
+            Some(block) => {
+                self.delete_block(&block);
+                (*block.rows().start(), block.columns().start)
+            }
+            None => {
+                let position = self.cursor_position();
+                let row = &self.rows[position.y()];
+                (position.y(), display_col(row, position.x(), tab_width))
+            }
+        };
+        let widths: Vec<usize> = lines
+            .iter()
+            .map(|line| display_col(line, line.len(), tab_width))
+            .collect();
+        let width = widths.iter().copied().max().unwrap_or_default();
+
+        for (i, line) in lines.iter().enumerate() {
+            let y = top + i;
+            if y == self.rows.len() {
+                let end = Coordinates::new(self.rows[y - 1].len(), y - 1);
+                self.rows.push(String::new());
+                self.record(Change::new(end, end, Coordinates::new(0, y)));
+            }
+            let row = &mut self.rows[y];
+            let row_width = display_col(row, row.len(), tab_width);
+            if line.is_empty() && row_width <= col {
+                continue;
+            }
+            let x = pad_to(row, col, tab_width);
+            // the text after the block stays aligned
+            let padding = match x < row.len() {
+                true => width - widths[i],
+                false => 0,
+            };
+            let text = format!("{}{}", line, " ".repeat(padding));
+            row.insert_str(x, &text);
+            let at = Coordinates::new(x, y);
+            self.record(Change::new(at, at, Coordinates::new(x + text.len(), y)));
+        }
         self.has_changed = true;
-        let current_row_index = self.cursor.y() + self.screen.get_row_offset();
-        let current_col_index = self.cursor.x() + self.screen.get_col_offset();
-
-        let mut row: String = self.get_row(current_row_index);
+        let x = byte_at_col(&self.rows[top], col, tab_width);
+        self.move_to(Coordinates::new(x, top));
+    }
 
-        if current_row_index as usize == self.rows.len().saturating_sub(1)
-            && current_col_index == self.cursor_end_of_line(current_row_index).x()
+    fn quit(&mut self) -> Result<(), IoError> {
+        if !self.has_changed
+            || self.pipe
+            || self.confirm("WARNING, files not saved. Do you really want to quit? [y/n]")?
         {
-            return;
-        } else if current_col_index == self.cursor_end_of_line(current_row_index).x() {
-            let next_row: String = self.get_row(current_row_index.saturating_add(1));
-            row += &next_row;
-
-            self.rows.remove(current_row_index as usize);
-            self.rows.remove(current_row_index as usize);
-            self.rows.insert(current_row_index as usize, row);
-        } else {
-            row.remove(current_col_index as usize);
-            self.rows.remove(current_row_index as usize);
-            self.rows.insert(current_row_index as usize, row);
+            self.exit()
         }
+        Ok(())
     }
 
-    fn save_file(&mut self) {
-        if self.file_name == "[New file]" {
-            match self.prompt_file_name() {
-                Ok(_) => (),
-                Err(err) => self.die(err),
+    /// Moves the cursor one character left or right, going through line ends, or one
+    /// row up or down, staying in the same column on the screen.
+    fn move_cursor(&mut self, code: KeyCode) {
+        let position = self.cursor_position();
+        let target = match code {
+            KeyCode::Left => step(&self.rows, position, Direction::Left),
+            KeyCode::Right => step(&self.rows, position, Direction::Right),
+            KeyCode::Up if position.y() > 0 => Some(self.same_column(position, position.y() - 1)),
+            KeyCode::Down if position.y() + 1 < self.rows.len() => {
+                Some(self.same_column(position, position.y() + 1))
             }
+            _ => None,
+        };
+        if let Some(target) = target {
+            self.move_to(target);
         }
+    }
 
-        let content = self.rows.join("\n");
+    /// Position on row `y` drawn in the same column as `position`.
+    fn same_column(&self, position: Coordinates<usize>, y: usize) -> Coordinates<usize> {
+        let tab_width = self.settings.tab_width;
+        let col = display_col(&self.rows[position.y()], position.x(), tab_width);
+        Coordinates::new(byte_at_col(&self.rows[y], col, tab_width), y)
+    }
 
-        match fs::write(&self.file_name, content) {
-            Ok(_) => (),
-            Err(_) => self.die("Error writing to file"),
+    fn insert_char(&mut self, ch: char) {
+        if !self.check_editable() {
+            return;
         }
-
-        match self.screen.set_status_msg("file saved.") {
-            Ok(_) => (),
-            Err(_) => self.die("Error in msg"),
+        let position = self.cursor_position();
+        let next = self.rows[position.y()][position.x()..].chars().next();
+        if next == Some(ch) && self.is_auto_closer(ch) {
+            self.move_cursor(KeyCode::Right);
+            return;
         }
+        let pair = self.pair_to_insert(ch, position);
 
-        self.has_changed = false;
+        self.dedent_before(ch);
+        let end = self.insert_text(self.cursor_position(), &ch.to_string());
+        self.move_to(end);
+        if let Some(close) = pair {
+            self.insert_text(self.cursor_position(), &close.to_string());
+        }
     }
 
-    fn prompt_file_name(&mut self) -> Result<(), IoError> {
-        let mut file_name = "".to_string();
-        loop {
-            match self
-                .screen
-                .set_status_msg(format!("File name: {}", file_name))
-            {
-                Ok(_) => (),
-                Err(_) => self.die("Error in msg"),
-            }
-
-            match self.read_key()? {
-                Some(c) => match c.code {
-                    KeyCode::Char(ch) => {
-                        file_name += &ch.to_string();
-                    }
-
-                    KeyCode::Enter => {
-                        self.file_name = file_name;
-                        return Ok(());
-                    }
-                    KeyCode::Backspace => {
-                        let _ = file_name.pop();
-                    }
-                    _ => (),
-                },
-                None => (),
-            }
+    /// Character closing `open` when it is typed, if pairs are inserted together.
+    fn closing_pair(&self, open: char) -> Option<char> {
+        if !self.settings.auto_pairs {
+            return None;
         }
+        let quotes = self
+            .filetype
+            .map_or(&['"', '\''][..], |filetype| filetype.quotes);
+        closing_bracket(open).or_else(|| quotes.contains(&open).then_some(open))
     }
 
-    fn prompt_search(&mut self) -> Result<(), IoError> {
-        let mut search_term = "".to_string();
-        loop {
+    fn is_auto_closer(&self, ch: char) -> bool {
+        ['(', '[', '{', '"', '\'', '`']
+            .into_iter()
+            .any(|open| self.closing_pair(open) == Some(ch))
+    }
+
+    /// Closing character to insert after `ch` typed at `at`. Only before blanks or
+    /// closing brackets, so that typing in front of some text doesn't pair, and
+    /// quotes right after a word are taken as apostrophes or closing quotes.
+    fn pair_to_insert(&self, ch: char, at: Coordinates<usize>) -> Option<char> {
+        let close = self.closing_pair(ch)?;
+        let row = &self.rows[at.y()];
+        let next = row[at.x()..].chars().next();
+        if next.is_some_and(|next| !next.is_whitespace() && !")]}".contains(next)) {
+            return None;
+        }
+        let previous = row[..at.x()].chars().next_back();
+        if close == ch
+            && previous.is_some_and(|previous| previous.is_alphanumeric() || previous == ch)
+        {
+            return None;
+        }
+        Some(close)
+    }
+
+    /// Backspace between a pair with nothing inside deletes both characters.
+    fn delete_empty_pair(&mut self) -> bool {
+        let position = self.cursor_position();
+        let row = &self.rows[position.y()];
+        let (Some(open), Some(close)) = (
+            row[..position.x()].chars().next_back(),
+            row[position.x()..].chars().next(),
+        ) else {
+            return false;
+        };
+        if self.closing_pair(open) != Some(close) {
+            return false;
+        }
+        let start = Coordinates::new(position.x() - open.len_utf8(), position.y());
+        let end = Coordinates::new(position.x() + close.len_utf8(), position.y());
+        self.delete_range(start, end);
+        true
+    }
+
+    /// A closing bracket typed on a line holding only indentation goes one level back.
+    fn dedent_before(&mut self, ch: char) {
+        let closes_indent = self.settings.auto_indent
+            && self.filetype.is_some_and(|filetype| {
+                filetype
+                    .indent_after
+                    .iter()
+                    .any(|&open| closing_bracket(open) == Some(ch))
+            });
+        if !closes_indent {
+            return;
+        }
+
+        let position = self.cursor_position();
+        let before = &self.rows[position.y()][..position.x()];
+        if before.is_empty() || !before.trim_start().is_empty() {
+            return;
+        }
+        let dedented = outdent(before, self.indent, self.settings.tab_width).len();
+        self.delete_range(Coordinates::new(dedented, position.y()), position);
+    }
+
+    fn insert_enter(&mut self) {
+        if !self.check_editable() {
+            return;
+        }
+        if self.settings.auto_indent {
+            self.insert_indented_line();
+            return;
+        }
+        let end = self.insert_text(self.cursor_position(), "\n");
+        self.move_to(end);
+    }
+
+    /// Breaks the line at the cursor, keeping the indentation of the line and adding a
+    /// level after an opening bracket (or whatever the filetype indents after). Between
+    /// a pair of brackets, the closing one goes on its own line.
+    fn insert_indented_line(&mut self) {
+        let position = self.cursor_position();
+        let row = &self.rows[position.y()];
+        let (before, after) = row.split_at(position.x());
+        let indent = leading_whitespace(before).to_string();
+        let opener = before.trim_end().chars().next_back().filter(|ch| {
+            self.filetype
+                .is_some_and(|filetype| filetype.indent_after.contains(ch))
+        });
+        let split_pair = opener
+            .and_then(closing_bracket)
+            .is_some_and(|close| after.trim_start().starts_with(close));
+
+        // the text moving to the new line starts at the indentation
+        let blanks = after.len() - after.trim_start().len();
+        let blanks_end = Coordinates::new(position.x() + blanks, position.y());
+        self.delete_range(position, blanks_end);
+
+        let mut inner = indent.clone();
+        if opener.is_some() {
+            inner += &self.indent.unit();
+        }
+        let end = self.insert_text(position, &format!("\n{}", inner));
+        if split_pair {
+            self.insert_text(end, &format!("\n{}", indent));
+        }
+        self.move_to(end);
+    }
+
+    fn process_backspace(&mut self) {
+        if !self.check_editable() {
+            return;
+        }
+        if self.delete_empty_pair() {
+            return;
+        }
+        let position = self.cursor_position();
+        if let Some(previous) = step(&self.rows, position, Direction::Left) {
+            self.delete_range(previous, position);
+        }
+    }
+
+    fn process_delete(&mut self) {
+        if !self.check_editable() {
+            return;
+        }
+        let position = self.cursor_position();
+        if let Some(next) = step(&self.rows, position, Direction::Right) {
+            self.delete_range(position, next);
+        }
+    }
+
+    fn paste(&mut self, text: &str) {
+        if !self.check_editable() {
+            return;
+        }
+        let text = text.replace('\r', "");
+        let end = self.insert_text(self.cursor_position(), &text);
+        self.move_to(end);
+    }
+
+    /// Inserts `text` at `at` and returns the position right after it.
+    fn insert_text(&mut self, at: Coordinates<usize>, text: &str) -> Coordinates<usize> {
+        self.has_changed = true;
+        let tail = self.rows[at.y()].split_off(at.x());
+
+        let mut lines = text.split('\n');
+        let mut y = at.y();
+        self.rows[y] += lines.next().unwrap_or_default();
+        for line in lines {
+            y += 1;
+            self.rows.insert(y, line.to_string());
+        }
+
+        let end = Coordinates::new(self.rows[y].len(), y);
+        self.rows[y] += &tail;
+        self.record(Change::new(at, at, end));
+        end
+    }
+
+    fn check_editable(&mut self) -> bool {
+        if self.read_only {
             match self
                 .screen
-                .set_status_msg(format!("Search: {}", search_term))
+                .set_status_msg("Read-only mode, Alt-R to allow editing.")
             {
                 Ok(_) => (),
                 Err(_) => self.die("Error in msg"),
             }
+        }
+        !self.read_only
+    }
 
-            match self.read_key()? {
-                Some(c) => match c.code {
-                    KeyCode::Char(ch) => {
-                        search_term += &ch.to_string();
-                    }
+    fn toggle_read_only(&mut self) {
+        self.read_only = !self.read_only;
+        let msg = if self.read_only {
+            "Read-only mode enabled."
+        } else {
+            "Read-only mode disabled."
+        };
+        match self.screen.set_status_msg(msg) {
+            Ok(_) => (),
+            Err(_) => self.die("Error in msg"),
+        }
+    }
 
-                    KeyCode::Enter => {
-                        match self.find(&search_term) {
-                            Ok(_) => (),
-                            Err(_) => self.die("Error in find"),
-                        }
+    fn move_word(&mut self, direction: Direction) {
+        let target = word_boundary(&self.rows, self.cursor_position(), direction);
+        self.move_to(target);
+    }
+
+    fn delete_word(&mut self, direction: Direction) {
+        if !self.check_editable() {
+            return;
+        }
+        let position = self.cursor_position();
+        let target = word_boundary(&self.rows, position, direction);
+
+        if direction == Direction::Left {
+            self.delete_range(target, position);
+        } else {
+            self.delete_range(position, target);
+        }
+    }
+
+    /// First and last lines of the selection, or the line of the cursor.
+    fn selected_lines(&self) -> (usize, usize) {
+        let y = self.cursor_position().y();
+        match self.selection_range() {
+            // a selection ending at the start of a line doesn't include it
+            Some((start, end)) if end.x() == 0 && end.y() > start.y() => (start.y(), end.y() - 1),
+            Some((start, end)) => (start.y(), end.y()),
+            None => (y, y),
+        }
+    }
+
+    /// Moves the cursor and the selection anchor `lines` rows down (up when negative).
+    fn shift_lines(&mut self, lines: isize) {
+        let position = self.cursor_position();
+        let shift = |pos: Coordinates<usize>| {
+            Coordinates::new(pos.x(), pos.y().saturating_add_signed(lines))
+        };
+        self.selection_anchor = self.selection_anchor.map(shift);
+        self.move_to(shift(position));
+    }
+
+    /// Swaps the current line, or the selected ones, with the line above or below.
+    fn move_lines(&mut self, down: bool) {
+        if !self.check_editable() {
+            return;
+        }
+        let (first, last) = self.selected_lines();
+        let (top, bottom) = if down && last + 1 < self.rows.len() {
+            (first, last + 1)
+        } else if !down && first > 0 {
+            (first - 1, last)
+        } else {
+            return;
+        };
+        // the other cursors in the swapped lines go to their end
+        let old_end = Coordinates::new(self.rows[bottom].len(), bottom);
+        if down {
+            let row = self.rows.remove(bottom);
+            self.rows.insert(top, row);
+        } else {
+            let row = self.rows.remove(top);
+            self.rows.insert(bottom, row);
+        }
+        let new_end = Coordinates::new(self.rows[bottom].len(), bottom);
+        self.record(Change::new(Coordinates::new(0, top), old_end, new_end));
+        self.has_changed = true;
+        self.shift_lines(if down { 1 } else { -1 });
+    }
+
+    /// Copies the current line, or the selected ones, below and moves to the copy.
+    fn duplicate_lines(&mut self) {
+        if !self.check_editable() {
+            return;
+        }
+        let (first, last) = self.selected_lines();
+        let copy = self.rows[first..=last].to_vec();
+        self.rows.splice(last + 1..last + 1, copy);
+        let below = Coordinates::new(0, last + 1);
+        self.record(Change::new(
+            below,
+            below,
+            Coordinates::new(0, 2 * last + 2 - first),
+        ));
+        self.has_changed = true;
+        self.shift_lines((last + 1 - first) as isize);
+    }
+
+    fn delete_lines(&mut self) {
+        if !self.check_editable() {
+            return;
+        }
+        let (first, last) = self.selected_lines();
+        let x = self.cursor_position().x();
+        self.rows.drain(first..=last);
+        if self.rows.is_empty() {
+            self.rows.push("".to_string());
+        }
+        let start = Coordinates::new(0, first);
+        self.record(Change::new(start, Coordinates::new(0, last + 1), start));
+        self.has_changed = true;
+        self.selection_anchor = None;
+        self.move_to(Coordinates::new(x, first));
+    }
+
+    /// Joins the current line with the next one, or the selected lines together,
+    /// leaving a single space between them. The cursor goes to the last join.
+    fn join_lines(&mut self) {
+        if !self.check_editable() {
+            return;
+        }
+        let (first, last) = self.selected_lines();
+        let last = last.max(first + 1).min(self.rows.len() - 1);
+        if first == last {
+            return;
+        }
+
+        let mut joint = self.rows[first].len();
+        for _ in first..last {
+            let row = self.rows.remove(first + 1);
+            let next = row.trim_start();
+            let end = self.rows[first].trim_end().len();
+            let space = if end > 0 && !next.is_empty() { " " } else { "" };
+            self.record(Change::new(
+                Coordinates::new(end, first),
+                Coordinates::new(row.len() - next.len(), first + 1),
+                Coordinates::new(end + space.len(), first),
+            ));
+            let joined = &mut self.rows[first];
+            joined.truncate(end);
+            joined.push_str(space);
+            joint = joined.len();
+            joined.push_str(next);
+        }
+        self.has_changed = true;
+        self.selection_anchor = None;
+        self.move_to(Coordinates::new(joint, first));
+    }
+
+    /// Inserts a level of indentation at the cursor, or in front of every selected line.
+    fn indent(&mut self) {
+        if !self.check_editable() {
+            return;
+        }
+        if self.selection_anchor.is_some() {
+            let unit = self.indent.unit();
+            let (first, last) = self.selected_lines();
+            self.edit_line_starts(first, last, |whitespace| format!("{}{}", whitespace, unit));
+            return;
+        }
+
+        let position = self.cursor_position();
+        let text = match self.indent {
+            IndentStyle::Tabs => "\t".to_string(),
+            // up to the next multiple of the indentation width
+            IndentStyle::Spaces(width) => {
+                let col = display_col(
+                    &self.rows[position.y()],
+                    position.x(),
+                    self.settings.tab_width,
+                );
+                " ".repeat(width - col % width)
+            }
+        };
+        let end = self.insert_text(position, &text);
+        self.move_to(end);
+    }
+
+    /// Removes a level of indentation from the current line or the selected ones.
+    fn outdent_lines(&mut self) {
+        if !self.check_editable() {
+            return;
+        }
+        let (first, last) = self.selected_lines();
+        let style = self.indent;
+        let tab_width = self.settings.tab_width;
+        self.edit_line_starts(first, last, |whitespace| {
+            outdent(whitespace, style, tab_width).to_string()
+        });
+    }
+
+    /// Replaces the indentation of the non blank lines from `first` to `last` with
+    /// what `edit` makes of it, keeping the cursor and the selection on the same text.
+    fn edit_line_starts(
+        &mut self,
+        first: usize,
+        last: usize,
+        mut edit: impl FnMut(&str) -> String,
+    ) {
+        let mut cursor = self.cursor_position();
+        for y in first..=last {
+            let row = &self.rows[y];
+            let whitespace = leading_whitespace(row);
+            if whitespace.len() == row.len() {
+                continue;
+            }
+            let indented = edit(whitespace);
+            if indented == whitespace {
+                continue;
+            }
+            let old_len = whitespace.len();
+            let added = indented.len() as isize - old_len as isize;
+            self.rows[y].replace_range(..old_len, &indented);
+            self.has_changed = true;
+            self.record(Change::new(
+                Coordinates::new(old_len.min(indented.len()), y),
+                Coordinates::new(old_len, y),
+                Coordinates::new(indented.len(), y),
+            ));
+
+            // shifted from the end of the shorter indentation
+            let at = Coordinates::new(old_len.min(indented.len()), y);
+            cursor = shifted(cursor, at, added);
+            self.selection_anchor = self
+                .selection_anchor
+                .map(|anchor| shifted(anchor, at, added));
+        }
+        self.move_to(cursor);
+    }
+
+    /// Comments out the current line, or the selected ones, or uncomments them if they
+    /// all are comments. The markers go at the indentation of the least indented line.
+    fn toggle_comment(&mut self) {
+        if !self.check_editable() {
+            return;
+        }
+        let Some(syntax) = self.filetype.and_then(CommentSyntax::of) else {
+            self.set_status_msg("No comment syntax for this file");
+            return;
+        };
+
+        let position = self.cursor_position();
+        let (first, last) = self.selected_lines();
+        let lines: Vec<usize> = (first..=last)
+            .filter(|&y| !self.rows[y].trim().is_empty())
+            .collect();
+        let Some(column) = lines
+            .iter()
+            .map(|&y| leading_whitespace(&self.rows[y]).len())
+            .min()
+        else {
+            return;
+        };
+        let uncomment = lines.iter().all(|&y| syntax.is_commented(&self.rows[y]));
+
+        let mut cursor = position;
+        for y in lines {
+            let mut row = self.rows[y].clone();
+            let (at, added) = if uncomment {
+                syntax.uncomment(&mut row)
+            } else {
+                syntax.comment(&mut row, column)
+            };
+            self.replace_row(y, row);
+            let at = Coordinates::new(at, y);
+            cursor = shifted(cursor, at, added);
+            self.selection_anchor = self
+                .selection_anchor
+                .map(|anchor| shifted(anchor, at, added));
+        }
+        self.move_to(cursor);
+    }
+
+    /// Replaces row `y` with `row`, noting the change between the text they start
+    /// and end with.
+    fn replace_row(&mut self, y: usize, row: String) {
+        let old = &self.rows[y];
+        let prefix: usize = old
+            .chars()
+            .zip(row.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(ch, _)| ch.len_utf8())
+            .sum();
+        let suffix: usize = old[prefix..]
+            .chars()
+            .rev()
+            .zip(row[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(ch, _)| ch.len_utf8())
+            .sum();
+        self.record(Change::new(
+            Coordinates::new(prefix, y),
+            Coordinates::new(old.len() - suffix, y),
+            Coordinates::new(row.len() - suffix, y),
+        ));
+        self.rows[y] = row;
+        self.has_changed = true;
+    }
+
+    /// Text between `start` (included) and `end` (excluded), lines joined with `'\n'`.
+    fn text_range(&self, start: Coordinates<usize>, end: Coordinates<usize>) -> String {
+        if start.y() == end.y() {
+            return self.rows[start.y()][start.x()..end.x()].to_string();
+        }
+
+        let mut text = self.rows[start.y()][start.x()..].to_string();
+        for row in &self.rows[start.y() + 1..end.y()] {
+            text.push('\n');
+            text += row;
+        }
+        text.push('\n');
+        text += &self.rows[end.y()][..end.x()];
+        text
+    }
+
+    /// Removes the text between `start` (included) and `end` (excluded), leaving the
+    /// cursor at `start`. Returns the removed text.
+    fn delete_range(&mut self, start: Coordinates<usize>, end: Coordinates<usize>) -> String {
+        if start == end {
+            return "".to_string();
+        }
+        self.has_changed = true;
+        let text = self.text_range(start, end);
+
+        let tail = self.rows[end.y()][end.x()..].to_string();
+        self.rows[start.y()].truncate(start.x());
+        self.rows[start.y()] += &tail;
+        self.rows.drain(start.y() + 1..=end.y());
+        self.record(Change::new(start, end, start));
+
+        self.move_to(start);
+        text
+    }
+
+    /// The buffer as it is written out, with its line endings.
+    fn contents(&self) -> String {
+        let mut contents = self.rows.join(self.line_ending.as_str());
+        if self.final_newline {
+            contents += self.line_ending.as_str();
+        }
+        contents
+    }
+
+    fn save_file(&mut self) {
+        if !self.check_editable() {
+            return;
+        }
+
+        if self.file_name == "[New file]" || self.file_name == STDIN_NAME {
+            match self.prompt(PromptKind::FileName, "File name: ") {
+                Ok(Some(file_name)) if !file_name.is_empty() => self.rename(file_name),
+                Ok(_) => {
+                    self.set_status_msg("Save aborted.");
+                    return;
+                }
+                Err(err) => self.die(err),
+            }
+        }
+
+        if self.settings.trim_on_save {
+            self.trim_whitespace();
+        } else if self.settings.trim_trailing_whitespace {
+            self.trim_trailing_whitespace();
+        }
+        let bytes = match self.charset.encode(&self.contents()) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                self.set_status_msg(format!("Can't save {}: {}", self.file_name, err));
+                return;
+            }
+        };
+
+        if let Err(err) = fs::write(&self.file_name, bytes) {
+            match self
+                .screen
+                .set_status_msg(format!("Can't save {}: {}", self.file_name, err))
+            {
+                Ok(_) => (),
+                Err(_) => self.die("Error in msg"),
+            }
+            return;
+        }
+
+        match self.screen.set_status_msg("file saved.") {
+            Ok(_) => (),
+            Err(_) => self.die("Error in msg"),
+        }
+
+        self.has_changed = false;
+    }
+
+    fn trim_trailing_whitespace(&mut self) {
+        for row in &mut self.rows {
+            if row.ends_with(char::is_whitespace) {
+                row.truncate(row.trim_end().len());
+                self.has_changed = true;
+            }
+        }
+        // the cursor may have been in the removed whitespace
+        self.move_to(self.cursor_position());
+    }
+
+    /// Removes the blanks at the end of the lines and the empty lines at the end of
+    /// the file, which is left with exactly one final newline.
+    fn trim_whitespace(&mut self) {
+        self.trim_trailing_whitespace();
+        while self.rows.len() > 1 && self.rows.last().is_some_and(|row| row.is_empty()) {
+            self.rows.pop();
+            self.has_changed = true;
+        }
+        // an empty file stays empty, and `.editorconfig` can ask for no final newline
+        let wanted = self.editorconfig.insert_final_newline().unwrap_or(true);
+        if wanted && !self.final_newline && self.rows != [""] {
+            self.final_newline = true;
+            self.has_changed = true;
+        }
+        self.move_to(self.cursor_position());
+    }
+
+    fn confirm(&mut self, question: &str) -> Result<bool, IoError> {
+        match self.screen.set_status_msg(question) {
+            Ok(_) => (),
+            Err(_) => self.die("Error in status msg"),
+        }
+        Ok(matches!(
+            self.read_key()?
+                .unwrap_or(KeyEvent::new(KeyCode::End, KeyModifiers::CONTROL))
+                .code,
+            KeyCode::Char('y')
+        ))
+    }
+
+    fn prompt_open_file(&mut self) -> Result<(), IoError> {
+        let index = FileIndex::spawn(".");
+        let mut query = "".to_string();
+        let mut selected: usize = 0;
+
+        loop {
+            let max_matches = (self.screen.height as usize / 2).max(1);
+            let matches = index.matches(&query, max_matches);
+            selected = selected.min(matches.len().saturating_sub(1));
+
+            let indexing = if index.is_done() { "" } else { "..." };
+
+            self.refresh();
+            match self.screen.draw_popup(&matches, selected) {
+                Ok(_) => (),
+                Err(_) => self.die("Error drawing file list"),
+            }
+            match self.screen.set_status_msg(format!(
+                "Open ({}/{}{}): {}",
+                matches.len(),
+                index.len(),
+                indexing,
+                query
+            )) {
+                Ok(_) => (),
+                Err(_) => self.die("Error in msg"),
+            }
+
+            // redraw as the files come in, until a key is pressed
+            let shown = index.len();
+            let mut ready = index.is_done();
+            while !ready {
+                ready = poll(FILE_LIST_REFRESH).map_err(|_| IoError::new("Error in poll"))?;
+                if !ready && (index.is_done() || index.len() != shown) {
+                    break;
+                }
+            }
+            if !ready {
+                continue;
+            }
+
+            let Some(c) = self.read_key()? else {
+                continue;
+            };
+            let modified = c
+                .modifiers
+                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+            match c.code {
+                KeyCode::Char(ch) if !modified => {
+                    query.push(ch);
+                    selected = 0;
+                }
+                KeyCode::Backspace => {
+                    let _ = query.pop();
+                    selected = 0;
+                }
+                KeyCode::Up => selected = selected.saturating_sub(1),
+                KeyCode::Down => selected = selected.saturating_add(1),
+                KeyCode::Enter => {
+                    let Some(file) = matches.into_iter().nth(selected) else {
+                        continue;
+                    };
+                    if self.has_changed
+                        && !self.confirm(
+                            "WARNING, file not saved. Do you really want to open another? [y/n]",
+                        )?
+                    {
                         return Ok(());
                     }
-                    KeyCode::Backspace => {
-                        let _ = search_term.pop();
+                    self.load_file(file);
+                    return Ok(());
+                }
+                KeyCode::Esc => {
+                    return Ok(());
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn prompt_command(&mut self) -> Result<(), IoError> {
+        if let Some(line) = self.prompt(PromptKind::Command, ":")? {
+            self.execute_command_line(&line)?;
+        }
+        Ok(())
+    }
+
+    fn prompt(&mut self, kind: PromptKind, label: &str) -> Result<Option<String>, IoError> {
+        self.prompt_with(kind, label, |_, _| ())
+    }
+
+    /// Reads a line typed in the status bar after `label`, calling `on_change`
+    /// every time the input changes. Returns `None` when it is cancelled with Esc.
+    /// The command line also has Tab completion of the command names.
+    fn prompt_with(
+        &mut self,
+        kind: PromptKind,
+        label: &str,
+        mut on_change: impl FnMut(&mut Self, &str),
+    ) -> Result<Option<String>, IoError> {
+        let mut prompt = Prompt::new();
+        let mut completions: Option<(Vec<&str>, usize)> = None;
+        loop {
+            if self
+                .screen
+                .draw_prompt(label, prompt.input(), prompt.cursor())
+                .is_err()
+            {
+                self.die("Error in msg");
+            }
+
+            let Some(key) = self.read_key()? else {
+                continue;
+            };
+            let history = self.prompt_history.get(&kind).map_or(&[][..], |h| h);
+            let event = prompt.handle_key(key, history);
+            if event != PromptEvent::Complete {
+                completions = None;
+            }
+            match event {
+                PromptEvent::Changed => on_change(self, prompt.input()),
+                PromptEvent::Submit => {
+                    let input = prompt.input().to_string();
+                    let history = self.prompt_history.entry(kind).or_default();
+                    if !input.trim().is_empty() && history.last() != Some(&input) {
+                        history.push(input.clone());
                     }
-                    KeyCode::Esc => {
-                        return Ok(());
+                    return Ok(Some(input));
+                }
+                PromptEvent::Cancel => return Ok(None),
+                // Tab goes through the names starting with what was typed
+                PromptEvent::Complete
+                    if kind == PromptKind::Command && !prompt.input().contains(' ') =>
+                {
+                    let (names, index) = completions
+                        .get_or_insert_with(|| (ex::complete(prompt.input()), usize::MAX));
+                    if !names.is_empty() {
+                        *index = index.wrapping_add(1) % names.len();
+                        prompt.set_input(names[*index]);
+                        on_change(self, prompt.input());
                     }
-                    _ => (),
-                },
-                None => (),
+                }
+                _ => (),
             }
         }
     }
 
-    fn find(&mut self, term: &str) -> Result<(), IoError> {
-        let mut findings = vec![];
-        for (y, row) in self.rows.iter().enumerate() {
-            match row.find(term) {
-                Some(x) => findings.push(Coordinates::new(x, y)),
-                None => (),
+    fn execute_command_line(&mut self, line: &str) -> Result<(), IoError> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        let command = match line.parse::<ExCommand>() {
+            Ok(command) => command,
+            Err(err) => {
+                self.set_status_msg(err);
+                return Ok(());
             }
+        };
+
+        match command {
+            ExCommand::Write { path, quit } => {
+                if let Some(path) = path {
+                    self.rename(path);
+                }
+                self.save_file();
+                if quit && !self.has_changed {
+                    self.exit();
+                }
+            }
+            ExCommand::Edit { path, force } => {
+                if self.has_changed && !force {
+                    self.set_status_msg("No write since last change (add ! to override)");
+                } else {
+                    self.load_file(path);
+                }
+            }
+            ExCommand::Quit { force } => {
+                if self.has_changed && !force && !self.pipe {
+                    self.set_status_msg("No write since last change (add ! to override)");
+                } else {
+                    self.exit();
+                }
+            }
+            ExCommand::Set(assignment) => self.set_option(assignment.trim()),
+            ExCommand::GoTo(go_to) => {
+                let current_line = self.cursor_position().y();
+                match go_to.resolve(current_line, &self.rows) {
+                    Ok(coord) => self.go_to_coordinate(coord),
+                    Err(err) => self.set_status_msg(err),
+                }
+            }
+            ExCommand::Substitute(substitute) => self.substitute(&substitute),
+            ExCommand::Run(command) => self.run_command(command)?,
         }
+        Ok(())
+    }
+
+    fn substitute(&mut self, substitute: &Substitute) {
+        if !self.check_editable() {
+            return;
+        }
+        let lines = if substitute.whole_file {
+            0..self.rows.len()
+        } else {
+            let y = self.cursor_position().y();
+            y..y + 1
+        };
+
+        let mut changed = 0;
+        for y in lines {
+            if let Some(row) = substitute.apply(&self.rows[y]) {
+                self.replace_row(y, row);
+                changed += 1;
+            }
+        }
+        if changed == 0 {
+            self.set_status_msg(format!("Pattern not found: {}", substitute.pattern));
+            return;
+        }
+        let position = self.cursor_position();
+        self.move_to(position);
+        self.set_status_msg(format!("{} lines changed", changed));
+    }
+
+    /// Gives the buffer a new file name, used by the next save.
+    fn rename(&mut self, file_name: String) {
+        self.editorconfig = EditorConfig::for_file(&file_name);
+        self.file_name = file_name;
+        if let Err(err) = self.apply_settings() {
+            self.set_status_msg(err);
+        }
+    }
+
+    /// `:set` shows every option, `:set name?` shows one and `:set name=value`
+    /// changes it for the rest of the session.
+    fn set_option(&mut self, assignment: &str) {
+        if assignment.is_empty() {
+            let options: Vec<String> = OPTIONS
+                .into_iter()
+                .filter_map(|name| Some(format!("{}={}", name, self.settings.get(name).ok()?)))
+                .collect();
+            self.set_status_msg(options.join(" "));
+            return;
+        }
+        if let Some(name) = assignment.strip_suffix('?') {
+            let msg = match self.settings.get(name) {
+                Ok(value) => format!("{}={}", name, value),
+                Err(err) => err,
+            };
+            self.set_status_msg(msg);
+            return;
+        }
+
+        let previous = (self.config.settings.clone(), self.settings.clone());
+        let result = self
+            .config
+            .settings
+            .set_from_str(assignment)
+            .and_then(|_| self.settings.set_from_str(assignment))
+            .and_then(|_| self.update_screen());
+        if let Err(err) = result {
+            (self.config.settings, self.settings) = previous;
+            self.set_status_msg(err);
+        }
+        self.update_indent();
+    }
+
+    fn prompt_go_to(&mut self) -> Result<(), IoError> {
+        let Some(input) = self.prompt(PromptKind::GoTo, "Go to [line][:col]: ")? else {
+            return Ok(());
+        };
+        let current_line = (self.cursor.y() + self.screen.get_row_offset()) as usize;
+        let target = input
+            .parse::<GoTo>()
+            .and_then(|go_to| go_to.resolve(current_line, &self.rows));
+
+        match target {
+            Ok(coord) => self.go_to_coordinate(coord),
+            Err(err) => self.set_status_msg(err),
+        }
+        Ok(())
+    }
+
+    fn prompt_search(&mut self) -> Result<(), IoError> {
+        let origin = self.cursor_position();
+        let search_term = self.prompt_with(PromptKind::Search, "Search: ", |editor, term| {
+            editor.preview_search(origin, term)
+        })?;
+        self.search_term = None;
+
+        match search_term {
+            Some(search_term) => self.find(&search_term)?,
+            None => {
+                self.move_to(origin);
+                self.refresh();
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves to the first match of `term` from `origin` while it is being typed.
+    fn preview_search(&mut self, origin: Coordinates<usize>, term: &str) {
+        self.search_term = Some(term.to_string()).filter(|term| !term.is_empty());
+
+        let after = self.rows[origin.y()]
+            .get(origin.x()..)
+            .and_then(|rest| rest.find(term))
+            .map(|x| Coordinates::new(origin.x() + x, origin.y()));
+        let target = after.or_else(|| {
+            (1..=self.rows.len()).find_map(|i| {
+                let y = (origin.y() + i) % self.rows.len();
+                self.rows[y].find(term).map(|x| Coordinates::new(x, y))
+            })
+        });
+        self.move_to(target.unwrap_or(origin));
+        self.refresh();
+    }
 
-        let findings = self
+    fn find(&mut self, term: &str) -> Result<(), IoError> {
+        if term.is_empty() {
+            return Ok(());
+        }
+        let findings: Vec<Coordinates<usize>> = self
             .rows
             .iter()
             .enumerate()
-            .fold(vec![], |mut acc, (y, row)| {
-                match row.find(term) {
-                    Some(x) => acc.push(Coordinates::new(x, y)),
-                    None => (),
-                };
-                acc
-            });
+            .flat_map(|(y, row)| {
+                row.match_indices(term)
+                    .map(move |(x, _)| Coordinates::new(x, y))
+            })
+            .collect();
+
+        if findings.is_empty() {
+            self.set_status_msg(format!("No matches for '{}'", term));
+            return Ok(());
+        }
 
-        let mut finding: usize = 0;
+        let position = self.cursor_position();
+        let mut finding = findings
+            .iter()
+            .position(|finding| (finding.y(), finding.x()) >= (position.y(), position.x()))
+            .unwrap_or(0);
+        self.search_term = Some(term.to_string());
 
         loop {
             self.go_to_coordinate(findings[finding]);
-            match self.read_key()? {
-                Some(c) => match c.code {
+            if let Some(c) = self.read_key()? {
+                match c.code {
                     KeyCode::Up => {
                         if finding == 0 {
                             finding = findings.len().saturating_sub(1);
@@ -560,22 +2078,43 @@ impl Editor {
                             finding += 1;
                         }
                     }
-                    _ => return Ok(()),
-                },
-                None => (),
+                    _ => {
+                        self.search_term = None;
+                        return Ok(());
+                    }
+                }
             }
         }
     }
 
-    fn go_to_coordinate(&mut self, coord: Coordinates<usize>) {
-        let x = coord.x();
-        let y = coord.y();
+    fn jump_to_matching_bracket(&mut self) {
+        let position = self.cursor_position();
+        if !self.rows[position.y()][position.x()..].starts_with(is_bracket) {
+            self.set_status_msg("Not on a bracket");
+            return;
+        }
+        let tokens = match self.filetype {
+            Some(filetype) => highlight(&self.rows, filetype, self.rows.len()),
+            None => vec![],
+        };
+        let Some(target) = matching_bracket(&self.rows, &tokens, position) else {
+            self.set_status_msg("No matching bracket");
+            return;
+        };
 
-        let offset_row = y.saturating_sub(self.screen.height as usize / 2);
-        let true_y = y - offset_row;
+        let row_offset = self.screen.get_row_offset() as usize;
+        if (row_offset..row_offset + self.screen.height as usize).contains(&target.y()) {
+            self.move_to(target);
+        } else {
+            self.go_to_coordinate(target);
+        }
+    }
 
-        let offset_col = x.saturating_sub(self.screen.width as usize / 2);
-        let true_x = x - offset_col;
+    fn go_to_coordinate(&mut self, coord: Coordinates<usize>) {
+        let y = coord.y().min(self.rows.len().saturating_sub(1));
+        let col = display_col(&self.rows[y], coord.x(), self.settings.tab_width);
+        let offset_row = coord.y().saturating_sub(self.screen.height as usize / 2);
+        let offset_col = col.saturating_sub(self.screen.width as usize / 2);
 
         self.screen.reset_row_offset();
         self.screen.reset_column_offset();
@@ -583,20 +2122,494 @@ impl Editor {
         self.screen.scroll_down(offset_row.try_into().unwrap());
         self.screen.scroll_right(offset_col.try_into().unwrap());
 
-        self.cursor = Coordinates::new(true_x.try_into().unwrap(), true_y.try_into().unwrap());
-        match self.screen.refresh_screen(
-            &self.cursor,
-            &self.rows,
-            &self.file_name,
-            self.has_changed,
-        ) {
+        self.move_to(coord);
+        self.refresh();
+    }
+
+    /// Position of the cursor in the file, its column turned into a byte index.
+    fn cursor_position(&self) -> Coordinates<usize> {
+        let y = (self.cursor.y() + self.screen.get_row_offset()) as usize;
+        let col = (self.cursor.x() + self.screen.get_col_offset()) as usize;
+        let x = match self.rows.get(y) {
+            Some(row) => byte_at_col(row, col, self.settings.tab_width),
+            None => col,
+        };
+        Coordinates::new(x, y)
+    }
+
+    /// Moves the cursor to `coord`, scrolling only as much as needed to keep it on screen.
+    fn move_to(&mut self, coord: Coordinates<usize>) {
+        let y = coord.y().min(self.rows.len().saturating_sub(1));
+        let mut x = coord.x().min(self.rows[y].len());
+        while !self.rows[y].is_char_boundary(x) {
+            x -= 1;
+        }
+
+        let row_offset = self.screen.get_row_offset() as usize;
+        let height = self.screen.height as usize;
+        if y < row_offset {
+            self.screen.scroll_up((row_offset - y).try_into().unwrap());
+        } else if y >= row_offset + height {
+            self.screen
+                .scroll_down((y + 1 - row_offset - height).try_into().unwrap());
+        }
+
+        let col = display_col(&self.rows[y], x, self.settings.tab_width);
+        let col_offset = self.screen.get_col_offset() as usize;
+        let width = self.screen.width as usize;
+        if col < col_offset {
+            self.screen
+                .scroll_left((col_offset - col).try_into().unwrap());
+        } else if col >= col_offset + width {
+            self.screen
+                .scroll_right((col + 1 - col_offset - width).try_into().unwrap());
+        }
+
+        self.cursor = Coordinates::new(
+            (col - self.screen.get_col_offset() as usize)
+                .try_into()
+                .unwrap(),
+            (y - self.screen.get_row_offset() as usize)
+                .try_into()
+                .unwrap(),
+        );
+    }
+
+    /// Moves to the first non blank character of the line, or to the beginning of the
+    /// line if the cursor is already there.
+    fn move_home(&mut self) {
+        let position = self.cursor_position();
+        let row = &self.rows[position.y()];
+        let first_non_blank = row.len() - row.trim_start().len();
+
+        let x = if position.x() == first_non_blank {
+            0
+        } else {
+            first_non_blank
+        };
+        self.move_to(Coordinates::new(x, position.y()));
+    }
+
+    fn move_end(&mut self) {
+        let y = self.cursor_position().y();
+        self.move_to(Coordinates::new(self.rows[y].len(), y));
+    }
+
+    /// Scrolls a whole screen up or down, keeping the cursor in the same place of the screen.
+    fn move_page(&mut self, down: bool) {
+        let position = self.cursor_position();
+        let height = self.screen.height as usize;
+        let row_offset = self.screen.get_row_offset() as usize;
+        let last_row = self.rows.len().saturating_sub(1);
+
+        let (y, new_row_offset) = if down {
+            let max_offset = self.rows.len().saturating_sub(height).max(row_offset);
+            (
+                (position.y() + height).min(last_row),
+                (row_offset + height).min(max_offset),
+            )
+        } else {
+            (
+                position.y().saturating_sub(height),
+                row_offset.saturating_sub(height),
+            )
+        };
+
+        self.screen.reset_row_offset();
+        self.screen.scroll_down(new_row_offset.try_into().unwrap());
+        self.move_to(Coordinates::new(position.x(), y));
+    }
+
+    fn move_to_document_start(&mut self) {
+        self.move_to(Coordinates::origin());
+    }
+
+    fn move_to_document_end(&mut self) {
+        let y = self.rows.len().saturating_sub(1);
+        self.move_to(Coordinates::new(self.rows[y].len(), y));
+    }
+
+    fn refresh(&mut self) {
+        // edits made at the main cursor only, like trimming on save, can leave the
+        // others out of the text
+        if !self.cursors.is_empty() {
+            let cursors = std::mem::take(&mut self.cursors);
+            self.set_cursors(cursors);
+        }
+        let first_row = self.screen.get_row_offset() as usize;
+        let last_row = (first_row + self.screen.height as usize).min(self.rows.len());
+        let matches = match &self.search_term {
+            Some(term) if !term.is_empty() => (first_row..last_row)
+                .flat_map(|y| {
+                    self.rows[y]
+                        .match_indices(term.as_str())
+                        .map(move |(x, _)| {
+                            (Coordinates::new(x, y), Coordinates::new(x + term.len(), y))
+                        })
+                })
+                .collect(),
+            _ => vec![],
+        };
+        // finding the matching bracket may need the classes of every row
+        let position = self.cursor_position();
+        let on_bracket = self.rows[position.y()]
+            .get(position.x()..)
+            .is_some_and(|rest| rest.starts_with(is_bracket));
+        let tokens = match self.filetype {
+            Some(filetype) if on_bracket => highlight(&self.rows, filetype, self.rows.len()),
+            Some(filetype) => highlight(&self.rows, filetype, last_row),
+            None => vec![],
+        };
+        let bracket =
+            on_bracket.then(|| (position, matching_bracket(&self.rows, &tokens, position)));
+        let decorations = Decorations {
+            selection: self.selection_range(),
+            matches,
+            tokens,
+            bracket,
+            cursors: self.cursors.clone(),
+            block: self.block,
+        };
+        let status = StatusInfo {
+            file: &self.file_name,
+            changed: self.has_changed,
+            read_only: self.read_only,
+            mode: match (&self.vi, &self.emacs) {
+                _ if self.block.is_some() => self.block.map(|block| {
+                    format!("block {}x{}", block.columns().len(), block.rows().count())
+                }),
+                _ if !self.cursors.is_empty() => {
+                    Some(format!("{} cursors", self.cursors.len() + 1))
+                }
+                (Some(vi), _) => Some(vi.mode().to_string()),
+                (_, Some(emacs)) => emacs.pending(),
+                _ => None,
+            },
+            indent: self.indent,
+        };
+        match self
+            .screen
+            .refresh_screen(&self.cursor, &self.rows, &status, &decorations)
+        {
+            Ok(_) => (),
+            Err(_) => self.die("Error refreshing screen"),
+        }
+    }
+
+    /// Selected text, from the anchor to the cursor. In vi's Visual mode the
+    /// character under the cursor is selected too.
+    fn selection_range(&self) -> Option<(Coordinates<usize>, Coordinates<usize>)> {
+        let anchor = self.selection_anchor?;
+        let y = anchor.y().min(self.rows.len().saturating_sub(1));
+        let anchor = Coordinates::new(anchor.x().min(self.rows[y].len()), y);
+
+        let (start, end) = ordered(anchor, self.cursor_position());
+        if self.vi.is_some() {
+            let end = step(&self.rows, end, Direction::Right).unwrap_or(end);
+            return Some((start, end));
+        }
+        Some((start, end))
+    }
+
+    fn execute_vi(&mut self, command: ViCommand) -> Result<(), IoError> {
+        let position = self.cursor_position();
+        if !matches!(command, ViCommand::Key(_)) {
+            self.block = None;
+        }
+        match command {
+            ViCommand::Key(key) => self.process_key(key)?,
+            ViCommand::Move(motion, count) => {
+                let target = motion_target(&self.rows, position, motion, count);
+                self.move_to(target);
+            }
+            ViCommand::Operate(operator, target, count) => self.vi_operate(operator, target, count),
+            ViCommand::Insert(at) => self.vi_insert(at),
+            ViCommand::Paste { before, count } => self.vi_paste(before, count),
+            ViCommand::VisualStart => self.selection_anchor = Some(position),
+            ViCommand::VisualSelect(object) => {
+                if let Some((start, end)) = text_object_range(&self.rows, position, object) {
+                    if start != end {
+                        self.selection_anchor = Some(start);
+                        let last = step(&self.rows, end, Direction::Left).unwrap_or(end);
+                        self.move_to(last);
+                    }
+                }
+            }
+            ViCommand::VisualOperate(operator) => {
+                if let Some((start, end)) = self.selection_range() {
+                    self.selection_anchor = None;
+                    self.vi_apply(operator, start, end);
+                }
+            }
+            ViCommand::Normal => {
+                // like in vi, leaving Insert mode moves the cursor back onto the last character
+                if self.selection_anchor.take().is_none() && position.x() > 0 {
+                    self.move_cursor(KeyCode::Left);
+                }
+            }
+            ViCommand::CommandLine => self.prompt_command()?,
+            ViCommand::Repeat(count) => {
+                let Some((command, keys)) = self.vi.as_ref().and_then(|vi| vi.last_change(count))
+                else {
+                    return Ok(());
+                };
+                self.execute_vi(command)?;
+                for key in keys {
+                    self.process_key(key)?;
+                }
+                if command.enters_insert() {
+                    self.execute_vi(ViCommand::Normal)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn execute_emacs(&mut self, command: EmacsCommand) -> Result<(), IoError> {
+        let position = self.cursor_position();
+        if !matches!(command, EmacsCommand::Yank | EmacsCommand::YankPop) {
+            self.yank_range = None;
+        }
+
+        match command {
+            EmacsCommand::Key(key) => self.process_key(key)?,
+            EmacsCommand::Run(command) => self.run_command(command)?,
+            EmacsCommand::LineStart => self.move_to(Coordinates::new(0, position.y())),
+            EmacsCommand::KillLine { append } => {
+                if !self.check_editable() {
+                    return Ok(());
+                }
+                let end = if position.x() < self.rows[position.y()].len() {
+                    Coordinates::new(self.rows[position.y()].len(), position.y())
+                } else if position.y() + 1 < self.rows.len() {
+                    Coordinates::new(0, position.y() + 1)
+                } else {
+                    return Ok(());
+                };
+                let text = self.delete_range(position, end);
+                self.kill(text, append);
+            }
+            EmacsCommand::KillRegion { append } => {
+                if !self.check_editable() {
+                    return Ok(());
+                }
+                if let Some((start, end)) = self.selection_range() {
+                    self.selection_anchor = None;
+                    let text = self.delete_range(start, end);
+                    self.kill(text, append);
+                }
+            }
+            EmacsCommand::CopyRegion => {
+                if let Some((start, end)) = self.selection_range() {
+                    self.selection_anchor = None;
+                    let text = self.text_range(start, end);
+                    self.kill(text, false);
+                    self.set_status_msg("Copied region.");
+                }
+            }
+            EmacsCommand::Yank | EmacsCommand::YankPop => {
+                if !self.check_editable() {
+                    return Ok(());
+                }
+                let mut at = position;
+                if command == EmacsCommand::YankPop {
+                    let Some((start, end)) = self.yank_range else {
+                        return Ok(());
+                    };
+                    self.delete_range(start, end);
+                    at = start;
+                }
+
+                let Some(emacs) = self.emacs.as_mut() else {
+                    return Ok(());
+                };
+                let text = if command == EmacsCommand::Yank {
+                    emacs.kill_ring.yank()
+                } else {
+                    emacs.kill_ring.yank_pop()
+                };
+                let Some(text) = text.map(|text| text.to_string()) else {
+                    self.set_status_msg("Kill ring is empty.");
+                    return Ok(());
+                };
+
+                let end = self.insert_text(at, &text);
+                self.move_to(end);
+                self.yank_range = Some((at, end));
+            }
+            EmacsCommand::SetMark => {
+                self.selection_anchor = Some(position);
+                self.set_status_msg("Mark set.");
+            }
+            EmacsCommand::Cancel => {
+                self.selection_anchor = None;
+                self.set_status_msg("Quit.");
+            }
+            EmacsCommand::Undefined(msg) => self.set_status_msg(msg),
+        }
+        Ok(())
+    }
+
+    fn kill(&mut self, text: String, append: bool) {
+        if let Some(emacs) = self.emacs.as_mut() {
+            emacs.kill_ring.kill(text, append);
+        }
+    }
+
+    fn set_status_msg(&mut self, msg: impl Into<String>) {
+        match self.screen.set_status_msg(msg) {
             Ok(_) => (),
-            Err(_) => self.die("Error in refresh screen while going to coordinate"),
+            Err(_) => self.die("Error in msg"),
         }
     }
 
-    fn get_row(&self, y: u16) -> String {
-        self.rows.iter().nth(y as usize).unwrap().to_string()
+    fn vi_operate(&mut self, operator: Operator, target: Target, count: usize) {
+        let position = self.cursor_position();
+        let last_line = self.rows.len().saturating_sub(1);
+
+        match target {
+            Target::Line => {
+                let last = (position.y() + count.saturating_sub(1)).min(last_line);
+                self.vi_apply_lines(operator, position.y(), last);
+            }
+            Target::Motion(motion) => {
+                let target = motion_target(&self.rows, position, motion, count);
+                let (start, end) = ordered(position, target);
+                if motion.is_linewise() {
+                    self.vi_apply_lines(operator, start.y(), end.y());
+                } else if motion.is_inclusive() {
+                    let end = step(&self.rows, end, Direction::Right).unwrap_or(end);
+                    self.vi_apply(operator, start, end);
+                } else {
+                    self.vi_apply(operator, start, end);
+                }
+            }
+            Target::Object(object) => {
+                if let Some((start, end)) = text_object_range(&self.rows, position, object) {
+                    self.vi_apply(operator, start, end);
+                }
+            }
+        }
+    }
+
+    fn vi_apply(&mut self, operator: Operator, start: Coordinates<usize>, end: Coordinates<usize>) {
+        if operator == Operator::Yank {
+            self.register = Register {
+                text: self.text_range(start, end),
+                linewise: false,
+                block: false,
+            };
+            self.move_to(start);
+            return;
+        }
+        if !self.check_editable() {
+            return;
+        }
+        self.register = Register {
+            text: self.delete_range(start, end),
+            linewise: false,
+            block: false,
+        };
+    }
+
+    fn vi_apply_lines(&mut self, operator: Operator, first: usize, last: usize) {
+        if operator != Operator::Yank && !self.check_editable() {
+            return;
+        }
+        self.register = Register {
+            text: self.rows[first..=last].join("\n"),
+            linewise: true,
+            block: false,
+        };
+        if operator == Operator::Yank {
+            let x = self.cursor_position().x();
+            self.move_to(Coordinates::new(x, first));
+            return;
+        }
+
+        self.has_changed = true;
+        self.rows.drain(first..=last);
+        if operator == Operator::Change {
+            self.rows.insert(first, "".to_string());
+            self.move_to(Coordinates::new(0, first));
+            return;
+        }
+
+        if self.rows.is_empty() {
+            self.rows.push("".to_string());
+        }
+        let y = first.min(self.rows.len() - 1);
+        let target = motion_target(&self.rows, Coordinates::new(0, y), Motion::Line(y + 1), 1);
+        self.move_to(target);
+    }
+
+    fn vi_insert(&mut self, at: InsertAt) {
+        let position = self.cursor_position();
+        let target = match at {
+            InsertAt::Cursor => position,
+            InsertAt::After => motion_target(&self.rows, position, Motion::Right, 1),
+            InsertAt::LineStart => {
+                let row = &self.rows[position.y()];
+                Coordinates::new(row.len() - row.trim_start().len(), position.y())
+            }
+            InsertAt::LineEnd => motion_target(&self.rows, position, Motion::LineEnd, 1),
+            InsertAt::LineBelow | InsertAt::LineAbove => {
+                if !self.check_editable() {
+                    return;
+                }
+                let y = if at == InsertAt::LineBelow {
+                    position.y() + 1
+                } else {
+                    position.y()
+                };
+                let indent = if self.settings.auto_indent {
+                    leading_whitespace(&self.rows[position.y()]).to_string()
+                } else {
+                    "".to_string()
+                };
+                self.has_changed = true;
+                self.rows.insert(y, indent);
+                Coordinates::new(self.rows[y].len(), y)
+            }
+        };
+        self.move_to(target);
+    }
+
+    fn vi_paste(&mut self, before: bool, count: usize) {
+        if self.register.text.is_empty() && !self.register.linewise {
+            return;
+        }
+        if !self.check_editable() {
+            return;
+        }
+        let position = self.cursor_position();
+        let count = count.min(MAX_PASTE_COUNT);
+
+        if self.register.linewise {
+            let y = if before {
+                position.y()
+            } else {
+                position.y() + 1
+            };
+            self.has_changed = true;
+            let lines: Vec<&str> = self.register.text.split('\n').collect();
+            let pasted: Vec<String> = std::iter::repeat_n(lines, count)
+                .flatten()
+                .map(str::to_string)
+                .collect();
+            self.rows.splice(y..y, pasted);
+            let target = motion_target(&self.rows, position, Motion::Line(y + 1), 1);
+            self.move_to(target);
+        } else {
+            let at = if before {
+                position
+            } else {
+                motion_target(&self.rows, position, Motion::Right, 1)
+            };
+            let end = self.insert_text(at, &self.register.text.repeat(count));
+            let last = step(&self.rows, end, Direction::Left).unwrap_or(end);
+            self.move_to(last);
+        }
     }
 
     pub fn die<S: Into<String>>(&mut self, error: S) {
@@ -617,20 +2630,58 @@ impl Editor {
             Ok(_) => (),
             Err(_) => self.die("Error in reset screen"),
         }
-        match io::stdout().queue(event::DisableMouseCapture) {
-            Ok(_) => (),
-            Err(_) => self.die("Error in disabeling mouse Capture"),
-        }
-
         if disable_raw_mode().is_err() {
             println!("Error in dissabeling raw: {}", errno());
         }
+
+        if self.pipe && io::stdout().write_all(self.contents().as_bytes()).is_err() {
+            std::process::exit(1);
+        }
         std::process::exit(0);
     }
 }
 
-pub fn initialize_stdout() -> io::Result<Stdout> {
-    let mut stdout = io::stdout();
-    stdout.queue(event::EnableMouseCapture)?;
-    Ok(stdout)
+/// Output used to draw the editor. When stdout is not a terminal, because the
+/// editor is writing the buffer to a pipe, the terminal is reopened through
+/// `/dev/tty`. Crossterm already reads the keys from `/dev/tty` when stdin is not
+/// a terminal.
+pub fn initialize_output() -> io::Result<Box<dyn Write>> {
+    let mut output: Box<dyn Write> = if io::stdout().is_terminal() {
+        Box::new(io::stdout())
+    } else {
+        Box::new(fs::OpenOptions::new().write(true).open("/dev/tty")?)
+    };
+    output
+        .queue(event::EnableMouseCapture)?
+        .queue(event::EnableBracketedPaste)?;
+    Ok(output)
+}
+
+/// Sorts two positions in the order they appear in the file.
+fn ordered(
+    a: Coordinates<usize>,
+    b: Coordinates<usize>,
+) -> (Coordinates<usize>, Coordinates<usize>) {
+    if (a.y(), a.x()) <= (b.y(), b.x()) {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// Where `pos` goes when `added` bytes are inserted at `at`, or removed after it
+/// when negative: positions after the change follow the text.
+fn shifted(pos: Coordinates<usize>, at: Coordinates<usize>, added: isize) -> Coordinates<usize> {
+    if pos.y() != at.y() || pos.x() < at.x() {
+        return pos;
+    }
+    let x = (pos.x() as isize + added).max(at.x() as isize) as usize;
+    Coordinates::new(x, pos.y())
+}
+
+fn is_writable(file: &str) -> bool {
+    match fs::metadata(file) {
+        Ok(_) => fs::OpenOptions::new().append(true).open(file).is_ok(),
+        Err(_) => true,
+    }
 }
@@ -0,0 +1,518 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Settings;
+use crate::indent::IndentStyle;
+
+/// What ends the lines of a file when it is saved.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    /// Ending of the first line of `text`.
+    pub fn detect(text: &str) -> Self {
+        match text.find(['\n', '\r']) {
+            Some(i) if text[i..].starts_with("\r\n") => LineEnding::CrLf,
+            Some(i) if text[i..].starts_with('\r') => LineEnding::Cr,
+            _ => LineEnding::Lf,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+/// Encoding of a file on disk.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Charset {
+    #[default]
+    Utf8,
+    Utf8Bom,
+    Latin1,
+    Utf16Be,
+    Utf16Le,
+}
+
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+impl Charset {
+    /// Charset given away by the byte order mark at the start of `bytes`.
+    pub fn from_bom(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(UTF8_BOM) {
+            Some(Charset::Utf8Bom)
+        } else if bytes.starts_with(b"\xfe\xff") {
+            Some(Charset::Utf16Be)
+        } else if bytes.starts_with(b"\xff\xfe") {
+            Some(Charset::Utf16Le)
+        } else {
+            None
+        }
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Result<String, String> {
+        let invalid = || format!("the file is not valid {}", self.name());
+        match self {
+            Charset::Utf8 | Charset::Utf8Bom => {
+                let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+                String::from_utf8(bytes.to_vec()).map_err(|_| invalid())
+            }
+            Charset::Latin1 => Ok(bytes.iter().map(|&byte| byte as char).collect()),
+            Charset::Utf16Be | Charset::Utf16Le => {
+                let bytes = bytes
+                    .strip_prefix(b"\xfe\xff")
+                    .or_else(|| bytes.strip_prefix(b"\xff\xfe"))
+                    .unwrap_or(bytes);
+                if !bytes.len().is_multiple_of(2) {
+                    return Err(invalid());
+                }
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|pair| match self {
+                        Charset::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+                        _ => u16::from_le_bytes([pair[0], pair[1]]),
+                    })
+                    .collect();
+                String::from_utf16(&units).map_err(|_| invalid())
+            }
+        }
+    }
+
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, String> {
+        let bytes = match self {
+            Charset::Utf8 => text.as_bytes().to_vec(),
+            Charset::Utf8Bom => [UTF8_BOM, text.as_bytes()].concat(),
+            Charset::Latin1 => text
+                .chars()
+                .map(|ch| u8::try_from(ch).map_err(|_| format!("'{}' is not in latin1", ch)))
+                .collect::<Result<_, _>>()?,
+            Charset::Utf16Be => [0xfeff]
+                .into_iter()
+                .chain(text.encode_utf16())
+                .flat_map(u16::to_be_bytes)
+                .collect(),
+            Charset::Utf16Le => [0xfeff]
+                .into_iter()
+                .chain(text.encode_utf16())
+                .flat_map(u16::to_le_bytes)
+                .collect(),
+        };
+        Ok(bytes)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Charset::Utf8 => "utf-8",
+            Charset::Utf8Bom => "utf-8-bom",
+            Charset::Latin1 => "latin1",
+            Charset::Utf16Be => "utf-16be",
+            Charset::Utf16Le => "utf-16le",
+        }
+    }
+}
+
+/// Properties that the `.editorconfig` files of the directories above a file give
+/// to it, see <https://editorconfig.org>. Invalid values are ignored.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EditorConfig {
+    properties: HashMap<String, String>,
+}
+
+impl EditorConfig {
+    /// Reads the `.editorconfig` files from the directory of `file` up to the one
+    /// with `root = true`, the nearest ones taking precedence.
+    pub fn for_file(file: &str) -> Self {
+        let mut editorconfig = Self::default();
+        let Some(path) = absolute(Path::new(file)) else {
+            return editorconfig;
+        };
+
+        let mut files = vec![];
+        for dir in path.ancestors().skip(1) {
+            let Ok(text) = fs::read_to_string(dir.join(".editorconfig")) else {
+                continue;
+            };
+            let (root, sections) = parse(&text);
+            files.push((dir.to_path_buf(), sections));
+            if root {
+                break;
+            }
+        }
+
+        for (dir, sections) in files.into_iter().rev() {
+            let Ok(relative) = path.strip_prefix(&dir) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            for (glob, properties) in sections {
+                if !section_matches(&glob, &relative) {
+                    continue;
+                }
+                for (name, value) in properties {
+                    if value == "unset" {
+                        editorconfig.properties.remove(&name);
+                    } else {
+                        editorconfig.properties.insert(name, value);
+                    }
+                }
+            }
+        }
+        editorconfig
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(|value| value.as_str())
+    }
+
+    fn number(&self, name: &str) -> Option<usize> {
+        self.get(name)?.parse().ok().filter(|&n| n > 0)
+    }
+
+    fn boolean(&self, name: &str) -> Option<bool> {
+        self.get(name)?.parse().ok()
+    }
+
+    /// Indentation to use, `detected` is the one guessed from the file and gives the
+    /// style when only the size is set.
+    pub fn indent(&self, detected: Option<IndentStyle>) -> Option<IndentStyle> {
+        let size = match self.get("indent_size") {
+            Some("tab") => self.number("tab_width"),
+            _ => self.number("indent_size"),
+        };
+        match (self.get("indent_style"), size) {
+            (Some("tab"), _) => Some(IndentStyle::Tabs),
+            (Some("space"), size) => {
+                let width = size.or(match detected {
+                    Some(IndentStyle::Spaces(width)) => Some(width),
+                    _ => None,
+                });
+                Some(IndentStyle::Spaces(width.unwrap_or(4)))
+            }
+            (_, Some(_)) if detected == Some(IndentStyle::Tabs) => Some(IndentStyle::Tabs),
+            (_, Some(size)) => Some(IndentStyle::Spaces(size)),
+            _ => None,
+        }
+    }
+
+    /// `tab_width` defaults to `indent_size`.
+    pub fn tab_width(&self) -> Option<usize> {
+        self.number("tab_width").or(self.number("indent_size"))
+    }
+
+    pub fn end_of_line(&self) -> Option<LineEnding> {
+        match self.get("end_of_line")? {
+            "lf" => Some(LineEnding::Lf),
+            "crlf" => Some(LineEnding::CrLf),
+            "cr" => Some(LineEnding::Cr),
+            _ => None,
+        }
+    }
+
+    pub fn charset(&self) -> Option<Charset> {
+        match self.get("charset")? {
+            "utf-8" => Some(Charset::Utf8),
+            "utf-8-bom" => Some(Charset::Utf8Bom),
+            "latin1" => Some(Charset::Latin1),
+            "utf-16be" => Some(Charset::Utf16Be),
+            "utf-16le" => Some(Charset::Utf16Le),
+            _ => None,
+        }
+    }
+
+    pub fn insert_final_newline(&self) -> Option<bool> {
+        self.boolean("insert_final_newline")
+    }
+
+    /// Overrides the options that are set in the `.editorconfig` files.
+    pub fn apply(&self, settings: &mut Settings, detected_indent: Option<IndentStyle>) {
+        if let Some(indent) = self.indent(detected_indent) {
+            settings.indent = Some(indent);
+        }
+        if let Some(width) = self.tab_width().filter(|width| (1..=16).contains(width)) {
+            settings.tab_width = width;
+        }
+        if let Some(trim) = self.boolean("trim_trailing_whitespace") {
+            settings.trim_trailing_whitespace = trim;
+        }
+        match self.get("max_line_length") {
+            Some("off") => settings.max_line_length = 0,
+            Some(_) => {
+                if let Some(length) = self.number("max_line_length") {
+                    settings.max_line_length = length;
+                }
+            }
+            None => (),
+        }
+    }
+}
+
+fn absolute(path: &Path) -> Option<PathBuf> {
+    if path.is_absolute() {
+        Some(path.to_path_buf())
+    } else {
+        Some(env::current_dir().ok()?.join(path))
+    }
+}
+
+type Section = (String, Vec<(String, String)>);
+
+/// Whether the file has `root = true` before the first section, and the sections.
+/// Names and values are lowercased, they are case insensitive.
+fn parse(text: &str) -> (bool, Vec<Section>) {
+    let mut root = false;
+    let mut sections: Vec<Section> = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(glob) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            sections.push((glob.to_string(), vec![]));
+            continue;
+        }
+        let Some((name, value)) = line.split_once('=') else {
+            continue;
+        };
+        let name = name.trim().to_lowercase();
+        let value = value.trim().to_lowercase();
+        match sections.last_mut() {
+            Some((_, properties)) => properties.push((name, value)),
+            None if name == "root" => root = value == "true",
+            None => (),
+        }
+    }
+    (root, sections)
+}
+
+/// A glob without a `/` matches the file name in any directory, otherwise it
+/// matches the path from the directory of the `.editorconfig` file.
+fn section_matches(glob: &str, relative: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    if glob.contains(&'/') {
+        let glob = glob.strip_prefix(&['/']).unwrap_or(&glob);
+        glob_matches(glob, &relative.chars().collect::<Vec<_>>())
+    } else {
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        glob_matches(&glob, &name.chars().collect::<Vec<_>>())
+    }
+}
+
+/// EditorConfig globs: `*` (not crossing a `/`), `**`, `?`, `[abc]`, `[!abc]`,
+/// `{a,b}` and `{1..10}`.
+fn glob_matches(glob: &[char], text: &[char]) -> bool {
+    let Some((&first, rest)) = glob.split_first() else {
+        return text.is_empty();
+    };
+    match first {
+        '*' if rest.first() == Some(&'*') => {
+            (0..=text.len()).any(|i| glob_matches(&rest[1..], &text[i..]))
+        }
+        '*' => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| glob_matches(rest, &text[i..])),
+        '?' => text.first().is_some_and(|&ch| ch != '/') && glob_matches(rest, &text[1..]),
+        '[' => match rest.iter().skip(1).position(|&ch| ch == ']') {
+            Some(end) => {
+                let (class, rest) = (&rest[..end + 1], &rest[end + 2..]);
+                text.first()
+                    .is_some_and(|&ch| ch != '/' && class_matches(class, ch))
+                    && glob_matches(rest, &text[1..])
+            }
+            None => literal_matches('[', rest, text),
+        },
+        '{' => match closing_brace(rest) {
+            Some(end) => braces_match(&rest[..end], &rest[end + 1..], text),
+            None => literal_matches('{', rest, text),
+        },
+        '\\' if !rest.is_empty() => literal_matches(rest[0], &rest[1..], text),
+        ch => literal_matches(ch, rest, text),
+    }
+}
+
+fn literal_matches(ch: char, glob: &[char], text: &[char]) -> bool {
+    text.first() == Some(&ch) && glob_matches(glob, &text[1..])
+}
+
+fn class_matches(class: &[char], ch: char) -> bool {
+    let (negated, class) = match class.split_first() {
+        Some(('!', rest)) => (true, rest),
+        _ => (false, class),
+    };
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            found |= (class[i]..=class[i + 2]).contains(&ch);
+            i += 3;
+        } else {
+            found |= class[i] == ch;
+            i += 1;
+        }
+    }
+    found != negated
+}
+
+fn closing_brace(glob: &[char]) -> Option<usize> {
+    let mut depth = 0;
+    for (i, &ch) in glob.iter().enumerate() {
+        match ch {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+fn braces_match(inside: &[char], rest: &[char], text: &[char]) -> bool {
+    let inside_text: String = inside.iter().collect();
+    if let Some((low, high)) = inside_text.split_once("..") {
+        if let (Ok(low), Ok(high)) = (low.parse::<i64>(), high.parse::<i64>()) {
+            // every prefix of the text that is a number in the range
+            return (1..=text.len()).any(|len| {
+                let number: String = text[..len].iter().collect();
+                number
+                    .parse::<i64>()
+                    .is_ok_and(|n| (low..=high).contains(&n))
+                    && glob_matches(rest, &text[len..])
+            });
+        }
+    }
+
+    let mut alternatives = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, &ch) in inside.iter().enumerate() {
+        match ch {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(&inside[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    alternatives.push(&inside[start..]);
+    if alternatives.len() == 1 {
+        // a single word in braces is literal
+        let literal: Vec<char> = ['{']
+            .into_iter()
+            .chain(inside.iter().copied())
+            .chain(['}'])
+            .collect();
+        return text.starts_with(&literal) && glob_matches(rest, &text[literal.len()..]);
+    }
+    alternatives.into_iter().any(|alternative| {
+        let glob: Vec<char> = alternative.iter().chain(rest).copied().collect();
+        glob_matches(&glob, text)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editorconfig(properties: &[(&str, &str)]) -> EditorConfig {
+        EditorConfig {
+            properties: properties
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn globs() {
+        assert!(section_matches("*.rs", "src/main.rs"));
+        assert!(!section_matches("src/*.rs", "src/bin/main.rs"));
+        assert!(section_matches("src/**.rs", "src/bin/main.rs"));
+        assert!(section_matches("/src/**/*.rs", "src/bin/main.rs"));
+        assert!(section_matches("*.{js,ts}", "app.ts"));
+        assert!(!section_matches("*.{js,ts}", "app.rs"));
+        assert!(section_matches("{a,b{c,d}}.txt", "bd.txt"));
+        assert!(section_matches("{single}", "{single}"));
+        assert!(section_matches("file{1..10}", "file10"));
+        assert!(!section_matches("file{1..10}", "file11"));
+        assert!(section_matches("file{-3..3}", "file-2"));
+        assert!(section_matches("[a-c]x", "bx"));
+        assert!(!section_matches("[!a-c]x", "bx"));
+        assert!(section_matches("[!a-c]x", "dx"));
+        assert!(section_matches("?.md", "a.md"));
+        assert!(!section_matches("a?b", "a/b"));
+        assert!(section_matches("\\*", "*"));
+    }
+
+    #[test]
+    fn charsets_round_trip() {
+        let text = "héllo\nwörld ✓";
+        for charset in [
+            Charset::Utf8,
+            Charset::Utf8Bom,
+            Charset::Utf16Be,
+            Charset::Utf16Le,
+        ] {
+            let bytes = charset.encode(text).unwrap();
+            assert_eq!(charset.decode(&bytes).unwrap(), text);
+        }
+        let latin1 = Charset::Latin1.encode("héllo").unwrap();
+        assert_eq!(latin1, b"h\xe9llo");
+        assert_eq!(Charset::Latin1.decode(&latin1).unwrap(), "héllo");
+        assert!(Charset::Latin1.encode("✓").is_err());
+        assert!(Charset::Utf8.decode(b"\xe9").is_err());
+        assert!(Charset::Utf16Le.decode(b"\xff\xfea").is_err());
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        let bytes = Charset::Utf16Le.encode("a").unwrap();
+        assert_eq!(Charset::from_bom(&bytes), Some(Charset::Utf16Le));
+        assert_eq!(Charset::from_bom(b"\xef\xbb\xbfa"), Some(Charset::Utf8Bom));
+        assert_eq!(Charset::from_bom(b"a"), None);
+    }
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(LineEnding::detect("a\r\nb\n"), LineEnding::CrLf);
+        assert_eq!(LineEnding::detect("a\rb"), LineEnding::Cr);
+        assert_eq!(LineEnding::detect("a\nb\r\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a"), LineEnding::Lf);
+    }
+
+    #[test]
+    fn indentation() {
+        let tabs = editorconfig(&[("indent_style", "tab"), ("indent_size", "2")]);
+        assert_eq!(tabs.indent(None), Some(IndentStyle::Tabs));
+
+        let spaces = editorconfig(&[("indent_style", "space")]);
+        assert_eq!(spaces.indent(None), Some(IndentStyle::Spaces(4)));
+        assert_eq!(
+            spaces.indent(Some(IndentStyle::Spaces(2))),
+            Some(IndentStyle::Spaces(2))
+        );
+
+        let size = editorconfig(&[("indent_size", "tab"), ("tab_width", "8")]);
+        assert_eq!(size.indent(None), Some(IndentStyle::Spaces(8)));
+        assert_eq!(
+            size.indent(Some(IndentStyle::Tabs)),
+            Some(IndentStyle::Tabs)
+        );
+
+        assert_eq!(editorconfig(&[]).indent(None), None);
+        assert_eq!(editorconfig(&[("indent_size", "0")]).indent(None), None);
+    }
+}
@@ -0,0 +1,186 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::commands::Command;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmacsCommand {
+    /// Key handled by the default keymap
+    Key(KeyEvent),
+    Run(Command),
+    LineStart,
+    KillLine {
+        append: bool,
+    },
+    KillRegion {
+        append: bool,
+    },
+    CopyRegion,
+    Yank,
+    YankPop,
+    SetMark,
+    Cancel,
+    Undefined(String),
+}
+
+/// Killed text, most recent last. Yanking inserts the entry at `yank_index`,
+/// and `M-y` moves it to older entries.
+#[derive(Default)]
+pub struct KillRing {
+    entries: Vec<String>,
+    yank_index: usize,
+}
+
+const KILL_RING_MAX: usize = 60;
+
+impl KillRing {
+    pub fn kill(&mut self, text: String, append: bool) {
+        match self.entries.last_mut() {
+            Some(last) if append => *last += &text,
+            _ => {
+                self.entries.push(text);
+                if self.entries.len() > KILL_RING_MAX {
+                    self.entries.remove(0);
+                }
+            }
+        }
+        self.yank_index = self.entries.len().saturating_sub(1);
+    }
+
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yank_index = self.entries.len().checked_sub(1)?;
+        self.entries.last().map(|text| text.as_str())
+    }
+
+    pub fn yank_pop(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.yank_index = match self.yank_index {
+            0 => self.entries.len() - 1,
+            i => i - 1,
+        };
+        self.entries.get(self.yank_index).map(|text| text.as_str())
+    }
+}
+
+/// Emacs key layer: turns keys and `C-x` chords into commands for the editor.
+/// Keys without an Emacs meaning are passed to the default keymap.
+#[derive(Default)]
+pub struct Emacs {
+    pub kill_ring: KillRing,
+    prefix: bool,
+    last_was_kill: bool,
+    last_was_yank: bool,
+}
+
+impl Emacs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Chord typed so far, shown in the status bar while waiting for the rest of it.
+    pub fn pending(&self) -> Option<String> {
+        if self.prefix {
+            Some("C-x-".to_string())
+        } else {
+            None
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<EmacsCommand> {
+        let command = if self.prefix {
+            self.prefix = false;
+            self.handle_prefixed(key)
+        } else {
+            self.handle_single(key)
+        };
+
+        if let Some(command) = &command {
+            self.last_was_kill = matches!(
+                command,
+                EmacsCommand::KillLine { .. } | EmacsCommand::KillRegion { .. }
+            );
+            self.last_was_yank = matches!(command, EmacsCommand::Yank | EmacsCommand::YankPop);
+        }
+        command
+    }
+
+    fn handle_single(&mut self, key: KeyEvent) -> Option<EmacsCommand> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+
+        let command = match key.code {
+            KeyCode::Char(ch) if ctrl => match ch {
+                'x' => {
+                    self.prefix = true;
+                    return None;
+                }
+                'a' => EmacsCommand::LineStart,
+                'e' => EmacsCommand::Run(Command::MoveLineEnd),
+                'f' => EmacsCommand::Run(Command::MoveRight),
+                'b' => EmacsCommand::Run(Command::MoveLeft),
+                'n' => EmacsCommand::Run(Command::MoveDown),
+                'p' => EmacsCommand::Run(Command::MoveUp),
+                'd' => EmacsCommand::Run(Command::Delete),
+                'v' => EmacsCommand::Run(Command::MovePageDown),
+                's' => EmacsCommand::Run(Command::Find),
+                'k' => EmacsCommand::KillLine {
+                    append: self.last_was_kill,
+                },
+                'w' => EmacsCommand::KillRegion {
+                    append: self.last_was_kill,
+                },
+                'y' => EmacsCommand::Yank,
+                ' ' | '@' => EmacsCommand::SetMark,
+                'g' => EmacsCommand::Cancel,
+                _ => EmacsCommand::Key(key),
+            },
+            KeyCode::Char(ch) if alt => match ch {
+                'f' => EmacsCommand::Run(Command::MoveWordRight),
+                'b' => EmacsCommand::Run(Command::MoveWordLeft),
+                'd' => EmacsCommand::Run(Command::DeleteWordRight),
+                'v' => EmacsCommand::Run(Command::MovePageUp),
+                '<' => EmacsCommand::Run(Command::MoveFileStart),
+                '>' => EmacsCommand::Run(Command::MoveFileEnd),
+                'w' => EmacsCommand::CopyRegion,
+                'x' => EmacsCommand::Run(Command::Prompt),
+                'y' if self.last_was_yank => EmacsCommand::YankPop,
+                'y' => EmacsCommand::Undefined("Previous command was not a yank".to_string()),
+                'g' => EmacsCommand::Run(Command::GoTo),
+                _ => EmacsCommand::Key(key),
+            },
+            _ => EmacsCommand::Key(key),
+        };
+        Some(command)
+    }
+
+    fn handle_prefixed(&mut self, key: KeyEvent) -> Option<EmacsCommand> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        let command = match key.code {
+            KeyCode::Char('s') if ctrl => EmacsCommand::Run(Command::Save),
+            KeyCode::Char('c') if ctrl => EmacsCommand::Run(Command::Quit),
+            KeyCode::Char('f') if ctrl => EmacsCommand::Run(Command::Open),
+            KeyCode::Char('q') if ctrl => EmacsCommand::Run(Command::ToggleReadOnly),
+            KeyCode::Char('g') if ctrl => EmacsCommand::Cancel,
+            KeyCode::Right => EmacsCommand::Run(Command::NextFile),
+            KeyCode::Left => EmacsCommand::Run(Command::PreviousFile),
+            _ => EmacsCommand::Undefined(format!("C-x {} is undefined", describe(key))),
+        };
+        Some(command)
+    }
+}
+
+fn describe(key: KeyEvent) -> String {
+    let name = match key.code {
+        KeyCode::Char(ch) => ch.to_string(),
+        code => format!("{:?}", code),
+    };
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        format!("C-{}", name)
+    } else if key.modifiers.contains(KeyModifiers::ALT) {
+        format!("M-{}", name)
+    } else {
+        name
+    }
+}
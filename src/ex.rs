@@ -0,0 +1,306 @@
+use std::str::FromStr;
+
+use crate::commands::Command;
+use crate::goto::GoTo;
+
+/// Command typed in the command line, like `w notes.txt` or `s/foo/bar/g`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExCommand {
+    Write { path: Option<String>, quit: bool },
+    Edit { path: String, force: bool },
+    Quit { force: bool },
+    Set(String),
+    GoTo(GoTo),
+    Substitute(Substitute),
+    Run(Command),
+}
+
+/// Names of the commands, offered by Tab completion together with the key
+/// binding actions.
+pub const EX_COMMANDS: [&str; 7] = ["write", "wq", "edit", "quit", "set", "goto", "substitute"];
+
+impl FromStr for ExCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let line = s.trim();
+        if line.starts_with(|ch: char| ch.is_ascii_digit() || ch == '+' || ch == '-') {
+            return Ok(ExCommand::GoTo(line.parse()?));
+        }
+        if let Some(substitute) = parse_substitute(line) {
+            return Ok(ExCommand::Substitute(substitute?));
+        }
+
+        let name_len = line
+            .find(|ch: char| !(ch.is_alphanumeric() || ch == '.' || ch == '_'))
+            .unwrap_or(line.len());
+        let (name, rest) = line.split_at(name_len);
+        let (force, rest) = match rest.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let arg = Some(rest.trim()).filter(|arg| !arg.is_empty());
+        let no_force = || {
+            if force {
+                Err(format!("'{}' does not take '!'", name))
+            } else {
+                Ok(())
+            }
+        };
+        let no_arg = || match arg {
+            Some(arg) => Err(format!("unexpected argument '{}' for '{}'", arg, name)),
+            None => Ok(()),
+        };
+
+        let command = match name {
+            "w" | "write" => ExCommand::Write {
+                path: arg.map(|arg| arg.to_string()),
+                quit: false,
+            },
+            "wq" | "x" => ExCommand::Write {
+                path: arg.map(|arg| arg.to_string()),
+                quit: true,
+            },
+            "e" | "edit" => ExCommand::Edit {
+                path: arg.ok_or("'edit' needs a file name")?.to_string(),
+                force,
+            },
+            "q" | "quit" => {
+                no_arg()?;
+                ExCommand::Quit { force }
+            }
+            "set" => ExCommand::Set(arg.unwrap_or_default().to_string()),
+            "goto" => match arg {
+                Some(arg) => ExCommand::GoTo(arg.parse()?),
+                None => ExCommand::Run(Command::GoTo),
+            },
+            _ => {
+                let command = name
+                    .parse::<Command>()
+                    .map_err(|_| format!("unknown command '{}'", name))?;
+                no_arg()?;
+                ExCommand::Run(command)
+            }
+        };
+        if !matches!(command, ExCommand::Edit { .. } | ExCommand::Quit { .. }) {
+            no_force()?;
+        }
+        Ok(command)
+    }
+}
+
+/// `s/pattern/replacement/flags` on the current line, or on every line with `%s`.
+/// Patterns are plain text, the `g` flag replaces every match instead of the first one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitute {
+    pub whole_file: bool,
+    pub pattern: String,
+    pub replacement: String,
+    pub global: bool,
+}
+
+impl Substitute {
+    /// Row with the replacements made, `None` when the pattern is not in it.
+    pub fn apply(&self, row: &str) -> Option<String> {
+        if !row.contains(&self.pattern) {
+            return None;
+        }
+        if self.global {
+            Some(row.replace(&self.pattern, &self.replacement))
+        } else {
+            Some(row.replacen(&self.pattern, &self.replacement, 1))
+        }
+    }
+}
+
+fn parse_substitute(line: &str) -> Option<Result<Substitute, String>> {
+    let (whole_file, rest) = match line.strip_prefix('%') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let rest = rest
+        .strip_prefix("substitute")
+        .or_else(|| rest.strip_prefix('s'))?;
+    let delimiter = rest
+        .chars()
+        .next()
+        .filter(|ch| !ch.is_alphanumeric() && !ch.is_whitespace())?;
+
+    let parts = split_escaped(&rest[delimiter.len_utf8()..], delimiter);
+    let (pattern, replacement, flags) = match parts.as_slice() {
+        [pattern, replacement] => (pattern, replacement, ""),
+        [pattern, replacement, flags] => (pattern, replacement, flags.as_str()),
+        _ => {
+            return Some(Err(format!(
+                "expected s{0}pattern{0}replacement{0}",
+                delimiter
+            )))
+        }
+    };
+    if pattern.is_empty() {
+        return Some(Err("the pattern is empty".to_string()));
+    }
+    let global = match flags {
+        "" => false,
+        "g" => true,
+        _ => return Some(Err(format!("unknown flags '{}'", flags))),
+    };
+
+    Some(Ok(Substitute {
+        whole_file,
+        pattern: pattern.clone(),
+        replacement: replacement.clone(),
+        global,
+    }))
+}
+
+/// Splits at `delimiter`, a backslash before it keeps it in the text.
+fn split_escaped(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' if chars.peek() == Some(&delimiter) => {
+                parts.last_mut().unwrap().push(delimiter);
+                chars.next();
+            }
+            ch if ch == delimiter => parts.push(String::new()),
+            ch => parts.last_mut().unwrap().push(ch),
+        }
+    }
+    parts
+}
+
+/// Command names starting with `prefix`, sorted.
+pub fn complete(prefix: &str) -> Vec<&'static str> {
+    let mut names: Vec<&str> = EX_COMMANDS
+        .into_iter()
+        .chain(Command::ALL.iter().map(|command| command.name()))
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::goto::LineTarget;
+
+    fn parse(line: &str) -> Result<ExCommand, String> {
+        line.parse()
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            parse("w"),
+            Ok(ExCommand::Write {
+                path: None,
+                quit: false
+            })
+        );
+        assert_eq!(
+            parse(" wq  notes.txt "),
+            Ok(ExCommand::Write {
+                path: Some("notes.txt".to_string()),
+                quit: true
+            })
+        );
+        assert_eq!(
+            parse("e! other.rs"),
+            Ok(ExCommand::Edit {
+                path: "other.rs".to_string(),
+                force: true
+            })
+        );
+        assert_eq!(parse("q!"), Ok(ExCommand::Quit { force: true }));
+        assert_eq!(
+            parse("set tab_width=4"),
+            Ok(ExCommand::Set("tab_width=4".to_string()))
+        );
+        assert_eq!(parse("goto"), Ok(ExCommand::Run(Command::GoTo)));
+        assert_eq!(
+            parse("move.file_end"),
+            Ok(ExCommand::Run(Command::MoveFileEnd))
+        );
+        assert_eq!(
+            parse("12:3"),
+            Ok(ExCommand::GoTo(GoTo {
+                line: LineTarget::Absolute(12),
+                col: Some(3)
+            }))
+        );
+        assert_eq!(
+            parse("-2"),
+            Ok(ExCommand::GoTo(GoTo {
+                line: LineTarget::Relative(-2),
+                col: None
+            }))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_commands() {
+        assert_eq!(
+            parse("frobnicate"),
+            Err("unknown command 'frobnicate'".to_string())
+        );
+        assert_eq!(parse("w! x"), Err("'w' does not take '!'".to_string()));
+        assert_eq!(
+            parse("q now"),
+            Err("unexpected argument 'now' for 'q'".to_string())
+        );
+        assert_eq!(parse("e"), Err("'edit' needs a file name".to_string()));
+    }
+
+    #[test]
+    fn parses_substitutions() {
+        assert_eq!(
+            parse("%s/a\\/b/c/g"),
+            Ok(ExCommand::Substitute(Substitute {
+                whole_file: true,
+                pattern: "a/b".to_string(),
+                replacement: "c".to_string(),
+                global: true,
+            }))
+        );
+        assert_eq!(
+            parse("s#x#y"),
+            Ok(ExCommand::Substitute(Substitute {
+                whole_file: false,
+                pattern: "x".to_string(),
+                replacement: "y".to_string(),
+                global: false,
+            }))
+        );
+        assert_eq!(parse("s//x/"), Err("the pattern is empty".to_string()));
+        assert_eq!(parse("s/a/b/i"), Err("unknown flags 'i'".to_string()));
+        assert_eq!(
+            parse("s/a"),
+            Err("expected s/pattern/replacement/".to_string())
+        );
+    }
+
+    #[test]
+    fn substitutes() {
+        let substitute = |global| Substitute {
+            whole_file: false,
+            pattern: "a".to_string(),
+            replacement: "bb".to_string(),
+            global,
+        };
+        assert_eq!(substitute(false).apply("aXa"), Some("bbXa".to_string()));
+        assert_eq!(substitute(true).apply("aXa"), Some("bbXbb".to_string()));
+        assert_eq!(substitute(true).apply("xyz"), None);
+    }
+
+    #[test]
+    fn completes_names() {
+        assert_eq!(complete("wr"), vec!["write"]);
+        assert!(complete("move.").contains(&"move.file_end"));
+        assert!(complete("").contains(&"quit"));
+        assert!(complete("zzz").is_empty());
+    }
+}
@@ -0,0 +1,499 @@
+use std::path::Path;
+
+/// Kind of file, found from its name. The name is used for the per-filetype
+/// sections of `config.toml`, the rest describes the language for highlighting.
+pub struct FileType {
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    file_names: &'static [&'static str],
+    pub line_comment: Option<&'static str>,
+    pub block_comment: Option<(&'static str, &'static str)>,
+    pub quotes: &'static [char],
+    pub keywords: &'static [&'static str],
+    pub types: &'static [&'static str],
+    /// Characters that increase the indentation of the next line when a line ends with them
+    pub indent_after: &'static [char],
+}
+
+const FILE_TYPES: [FileType; 21] = [
+    FileType {
+        name: "rust",
+        extensions: &["rs"],
+        file_names: &[],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"'],
+        keywords: RUST_KEYWORDS,
+        types: RUST_TYPES,
+        indent_after: &['{', '(', '['],
+    },
+    FileType {
+        name: "c",
+        extensions: &["c", "h"],
+        file_names: &[],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        keywords: C_KEYWORDS,
+        types: C_TYPES,
+        indent_after: &['{', '(', '['],
+    },
+    FileType {
+        name: "cpp",
+        extensions: &["cpp", "cc", "cxx", "hpp", "hh"],
+        file_names: &[],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        keywords: CPP_KEYWORDS,
+        types: C_TYPES,
+        indent_after: &['{', '(', '['],
+    },
+    FileType {
+        name: "go",
+        extensions: &["go"],
+        file_names: &[],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+        keywords: GO_KEYWORDS,
+        types: GO_TYPES,
+        indent_after: &['{', '(', '['],
+    },
+    FileType {
+        name: "java",
+        extensions: &["java"],
+        file_names: &[],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        keywords: JAVA_KEYWORDS,
+        types: JAVA_TYPES,
+        indent_after: &['{', '(', '['],
+    },
+    FileType {
+        name: "javascript",
+        extensions: &["js", "mjs", "cjs", "jsx"],
+        file_names: &[],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+        keywords: JS_KEYWORDS,
+        types: &[],
+        indent_after: &['{', '(', '['],
+    },
+    FileType {
+        name: "typescript",
+        extensions: &["ts", "tsx"],
+        file_names: &[],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+        keywords: JS_KEYWORDS,
+        types: TS_TYPES,
+        indent_after: &['{', '(', '['],
+    },
+    FileType {
+        name: "python",
+        extensions: &["py", "pyw"],
+        file_names: &[],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: &['"', '\''],
+        keywords: PYTHON_KEYWORDS,
+        types: PYTHON_TYPES,
+        indent_after: &['{', '(', '[', ':'],
+    },
+    FileType {
+        name: "ruby",
+        extensions: &["rb"],
+        file_names: &["Gemfile", "Rakefile"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: &['"', '\''],
+        keywords: RUBY_KEYWORDS,
+        types: &[],
+        indent_after: &['{', '(', '['],
+    },
+    FileType {
+        name: "shell",
+        extensions: &["sh", "bash", "zsh"],
+        file_names: &[".bashrc", ".zshrc", ".profile"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: &['"', '\''],
+        keywords: SHELL_KEYWORDS,
+        types: &[],
+        indent_after: &['{', '('],
+    },
+    FileType {
+        name: "make",
+        extensions: &["mk"],
+        file_names: &["Makefile", "makefile", "GNUmakefile"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: &[],
+        keywords: &[],
+        types: &[],
+        indent_after: &[],
+    },
+    FileType {
+        name: "toml",
+        extensions: &["toml"],
+        file_names: &[],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: &['"', '\''],
+        keywords: &["true", "false"],
+        types: &[],
+        indent_after: &['{', '(', '['],
+    },
+    FileType {
+        name: "yaml",
+        extensions: &["yml", "yaml"],
+        file_names: &[],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: &['"', '\''],
+        keywords: &["true", "false", "null"],
+        types: &[],
+        indent_after: &[':'],
+    },
+    FileType {
+        name: "json",
+        extensions: &["json"],
+        file_names: &[],
+        line_comment: None,
+        block_comment: None,
+        quotes: &['"'],
+        keywords: &["true", "false", "null"],
+        types: &[],
+        indent_after: &['{', '(', '['],
+    },
+    FileType {
+        name: "markdown",
+        extensions: &["md", "markdown"],
+        file_names: &[],
+        line_comment: None,
+        block_comment: Some(("<!--", "-->")),
+        quotes: &[],
+        keywords: &[],
+        types: &[],
+        indent_after: &[],
+    },
+    FileType {
+        name: "html",
+        extensions: &["html", "htm"],
+        file_names: &[],
+        line_comment: None,
+        block_comment: Some(("<!--", "-->")),
+        quotes: &['"'],
+        keywords: &[],
+        types: &[],
+        indent_after: &[],
+    },
+    FileType {
+        name: "xml",
+        extensions: &["xml", "svg"],
+        file_names: &[],
+        line_comment: None,
+        block_comment: Some(("<!--", "-->")),
+        quotes: &['"'],
+        keywords: &[],
+        types: &[],
+        indent_after: &[],
+    },
+    FileType {
+        name: "css",
+        extensions: &["css", "scss"],
+        file_names: &[],
+        line_comment: None,
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        keywords: &[],
+        types: &[],
+        indent_after: &['{', '(', '['],
+    },
+    FileType {
+        name: "lua",
+        extensions: &["lua"],
+        file_names: &[],
+        line_comment: Some("--"),
+        block_comment: Some(("--[[", "]]")),
+        quotes: &['"', '\''],
+        keywords: LUA_KEYWORDS,
+        types: &[],
+        indent_after: &['{', '(', '['],
+    },
+    FileType {
+        name: "sql",
+        extensions: &["sql"],
+        file_names: &[],
+        line_comment: Some("--"),
+        block_comment: Some(("/*", "*/")),
+        quotes: &['\''],
+        keywords: SQL_KEYWORDS,
+        types: SQL_TYPES,
+        indent_after: &['('],
+    },
+    FileType {
+        name: "haskell",
+        extensions: &["hs"],
+        file_names: &[],
+        line_comment: Some("--"),
+        block_comment: Some(("{-", "-}")),
+        quotes: &['"'],
+        keywords: HASKELL_KEYWORDS,
+        types: &[],
+        indent_after: &[],
+    },
+];
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+const RUST_TYPES: &[&str] = &[
+    "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64",
+    "i128", "isize", "f32", "f64", "String", "Vec", "Option", "Result", "Box", "Some", "None",
+    "Ok", "Err",
+];
+const C_KEYWORDS: &[&str] = &[
+    "break", "case", "const", "continue", "default", "do", "else", "enum", "extern", "for", "goto",
+    "if", "inline", "register", "return", "sizeof", "static", "struct", "switch", "typedef",
+    "union", "volatile", "while", "NULL",
+];
+const C_TYPES: &[&str] = &[
+    "void", "char", "short", "int", "long", "float", "double", "signed", "unsigned", "bool",
+    "size_t", "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t",
+    "uint64_t", "auto", "string",
+];
+const CPP_KEYWORDS: &[&str] = &[
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "constexpr",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "explicit",
+    "extern",
+    "false",
+    "for",
+    "friend",
+    "goto",
+    "if",
+    "inline",
+    "namespace",
+    "new",
+    "nullptr",
+    "operator",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "template",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typedef",
+    "typename",
+    "union",
+    "using",
+    "virtual",
+    "volatile",
+    "while",
+];
+const GO_KEYWORDS: &[&str] = &[
+    "break",
+    "case",
+    "chan",
+    "const",
+    "continue",
+    "default",
+    "defer",
+    "else",
+    "fallthrough",
+    "false",
+    "for",
+    "func",
+    "go",
+    "goto",
+    "if",
+    "import",
+    "interface",
+    "map",
+    "nil",
+    "package",
+    "range",
+    "return",
+    "select",
+    "struct",
+    "switch",
+    "true",
+    "type",
+    "var",
+];
+const GO_TYPES: &[&str] = &[
+    "bool", "byte", "error", "float32", "float64", "int", "int8", "int16", "int32", "int64",
+    "rune", "string", "uint", "uint8", "uint16", "uint32", "uint64", "uintptr", "any",
+];
+const JAVA_KEYWORDS: &[&str] = &[
+    "abstract",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "continue",
+    "default",
+    "do",
+    "else",
+    "enum",
+    "extends",
+    "false",
+    "final",
+    "finally",
+    "for",
+    "if",
+    "implements",
+    "import",
+    "instanceof",
+    "interface",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "synchronized",
+    "this",
+    "throw",
+    "throws",
+    "true",
+    "try",
+    "var",
+    "void",
+    "while",
+];
+const JAVA_TYPES: &[&str] = &[
+    "boolean", "byte", "char", "double", "float", "int", "long", "short", "String", "Object",
+];
+const JS_KEYWORDS: &[&str] = &[
+    "async",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "from",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "let",
+    "new",
+    "null",
+    "of",
+    "return",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "undefined",
+    "var",
+    "void",
+    "while",
+    "yield",
+];
+const TS_TYPES: &[&str] = &[
+    "any",
+    "boolean",
+    "interface",
+    "never",
+    "number",
+    "string",
+    "type",
+    "unknown",
+    "enum",
+];
+const PYTHON_KEYWORDS: &[&str] = &[
+    "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif",
+    "else", "except", "False", "finally", "for", "from", "global", "if", "import", "in", "is",
+    "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return", "self", "True", "try",
+    "while", "with", "yield",
+];
+const PYTHON_TYPES: &[&str] = &[
+    "bool", "bytes", "dict", "float", "int", "list", "object", "set", "str", "tuple",
+];
+const RUBY_KEYWORDS: &[&str] = &[
+    "begin", "break", "case", "class", "def", "do", "else", "elsif", "end", "ensure", "false",
+    "for", "if", "in", "module", "next", "nil", "not", "or", "and", "redo", "rescue", "retry",
+    "return", "self", "super", "then", "true", "unless", "until", "when", "while", "yield",
+];
+const SHELL_KEYWORDS: &[&str] = &[
+    "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if", "in",
+    "local", "return", "then", "until", "while",
+];
+const LUA_KEYWORDS: &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+const SQL_KEYWORDS: &[&str] = &[
+    "SELECT", "FROM", "WHERE", "INSERT", "INTO", "VALUES", "UPDATE", "SET", "DELETE", "CREATE",
+    "TABLE", "DROP", "ALTER", "JOIN", "LEFT", "RIGHT", "INNER", "OUTER", "ON", "AND", "OR", "NOT",
+    "NULL", "AS", "ORDER", "BY", "GROUP", "HAVING", "LIMIT", "PRIMARY", "KEY", "select", "from",
+    "where", "insert", "into", "values", "update", "set", "delete", "create", "table", "drop",
+    "alter", "join", "on", "and", "or", "not", "null", "as", "order", "by", "group", "limit",
+];
+const SQL_TYPES: &[&str] = &[
+    "INTEGER", "INT", "TEXT", "VARCHAR", "BOOLEAN", "REAL", "DATE", "integer", "int", "text",
+    "varchar", "boolean", "real", "date",
+];
+const HASKELL_KEYWORDS: &[&str] = &[
+    "case", "class", "data", "deriving", "do", "else", "if", "import", "in", "instance", "let",
+    "module", "newtype", "of", "then", "type", "where",
+];
+
+impl FileType {
+    pub fn detect(file_name: &str) -> Option<&'static FileType> {
+        let path = Path::new(file_name);
+        let name = path.file_name()?.to_str()?;
+        let extension = path.extension().and_then(|ext| ext.to_str());
+
+        FILE_TYPES.iter().find(|file_type| {
+            file_type.file_names.contains(&name)
+                || extension.is_some_and(|ext| file_type.extensions.contains(&ext))
+        })
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use ignore::WalkBuilder;
+
+/// List of the files under a directory, filled in by a background thread so the
+/// prompt stays responsive while big trees are being walked. The thread stops when
+/// the index is dropped.
+pub struct FileIndex {
+    files: Arc<Mutex<Vec<String>>>,
+    done: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+}
+
+impl FileIndex {
+    pub fn spawn<P: AsRef<Path>>(root: P) -> Self {
+        let files = Arc::new(Mutex::new(vec![]));
+        let done = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let root = root.as_ref().to_path_buf();
+        let thread_files = Arc::clone(&files);
+        let thread_done = Arc::clone(&done);
+        let thread_stop = Arc::clone(&stop);
+
+        thread::spawn(move || {
+            let walker = WalkBuilder::new(&root).require_git(false).build();
+            let mut batch = vec![];
+
+            for entry in walker.flatten() {
+                if thread_stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                if !entry.file_type().is_some_and(|kind| kind.is_file()) {
+                    continue;
+                }
+                let path = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+                batch.push(path.to_string_lossy().to_string());
+
+                if batch.len() >= 1024 {
+                    thread_files.lock().unwrap().append(&mut batch);
+                }
+            }
+
+            thread_files.lock().unwrap().append(&mut batch);
+            thread_done.store(true, Ordering::Release);
+        });
+
+        Self { files, done, stop }
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.lock().unwrap().len()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+
+    /// Returns the best `limit` files for `query`, best match first.
+    pub fn matches(&self, query: &str, limit: usize) -> Vec<String> {
+        let files = self.files.lock().unwrap();
+
+        let mut scored: Vec<(i64, &String)> = files
+            .iter()
+            .filter_map(|file| score(query, file).map(|score| (score, file)))
+            .collect();
+
+        scored.sort_unstable_by(|(score_a, file_a), (score_b, file_b)| {
+            score_b
+                .cmp(score_a)
+                .then(file_a.len().cmp(&file_b.len()))
+                .then(file_a.cmp(file_b))
+        });
+
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, file)| file.clone())
+            .collect()
+    }
+}
+
+impl Drop for FileIndex {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+const MATCH: i64 = 16;
+const CONSECUTIVE_BONUS: i64 = 16;
+const BOUNDARY_BONUS: i64 = 24;
+const FILE_NAME_BONUS: i64 = 8;
+const GAP_PENALTY: i64 = 2;
+
+/// Scores `candidate` against `query`, or returns `None` if the characters of the
+/// query do not appear in order in the candidate.
+///
+/// Matching is case insensitive unless the query contains an uppercase letter.
+/// Consecutive characters, characters at the start of a word and characters in
+/// the file name (after the last `/`) score higher, gaps between matches score lower.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let case_sensitive = query.chars().any(|ch| ch.is_uppercase());
+    let normalize = |ch: char| {
+        if case_sensitive {
+            ch
+        } else {
+            ch.to_ascii_lowercase()
+        }
+    };
+
+    let file_name_start = candidate.rfind('/').map_or(0, |i| i + 1);
+    let mut query_chars = query.chars().map(normalize).peekable();
+    let mut total = 0;
+    // in characters, so that multibyte ones can be consecutive
+    let mut last_match: Option<usize> = None;
+    let mut prev: Option<char> = None;
+
+    for (i, (byte, ch)) in candidate.char_indices().enumerate() {
+        let Some(&wanted) = query_chars.peek() else {
+            break;
+        };
+
+        if normalize(ch) == wanted {
+            total += MATCH;
+
+            match last_match {
+                Some(last) if last + 1 == i => total += CONSECUTIVE_BONUS,
+                Some(last) => total -= GAP_PENALTY * (i - last - 1).min(8) as i64,
+                None => (),
+            }
+
+            let is_boundary = match prev {
+                None => true,
+                Some(prev) => {
+                    matches!(prev, '/' | '_' | '-' | '.' | ' ')
+                        || (prev.is_lowercase() && ch.is_uppercase())
+                }
+            };
+            if is_boundary {
+                total += BOUNDARY_BONUS;
+            }
+            if byte >= file_name_start {
+                total += FILE_NAME_BONUS;
+            }
+
+            last_match = Some(i);
+            query_chars.next();
+        }
+        prev = Some(ch);
+    }
+
+    if query_chars.peek().is_some() {
+        return None;
+    }
+
+    Some(total - candidate.len() as i64 / 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_in_order() {
+        assert!(score("edr", "src/editor.rs").is_some());
+        assert!(score("rde", "src/editor.rs").is_none());
+        assert!(score("editors", "src/editor.rs").is_some());
+        assert!(score("editorz", "src/editor.rs").is_none());
+        assert!(score("", "src/editor.rs").is_some());
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(score("readme", "README.md").is_some());
+        assert!(score("README", "README.md").is_some());
+        assert!(score("Readme", "README.md").is_none());
+    }
+
+    #[test]
+    fn ranks_better_matches_higher() {
+        // consecutive characters
+        assert!(score("edit", "src/editor.rs") > score("edit", "src/exdxixt.rs"));
+        assert_eq!(score("éè", "éè.rs"), score("ab", "ab.rss"));
+        // in the file name rather than the directories
+        assert!(score("main", "src/main.rs") > score("main", "main/lib.rs"));
+        // at the start of a word
+        assert!(score("fb", "foo_bar.rs") > score("fb", "fabric.rs"));
+        assert!(score("fb", "fooBar.rs") > score("fb", "fabric.rs"));
+        // shorter candidates
+        assert!(score("lib", "lib.rs") > score("lib", "lib_with_a_long_name.rs"));
+    }
+}
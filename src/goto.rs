@@ -0,0 +1,80 @@
+use std::str::FromStr;
+
+use crate::coords::Coordinates;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineTarget {
+    Absolute(usize),
+    Relative(isize),
+}
+
+/// A position typed by the user as `line`, `line:col`, `+lines` or `-lines`.
+/// Lines and columns are 1-based, like the ones printed by compilers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GoTo {
+    pub line: LineTarget,
+    pub col: Option<usize>,
+}
+
+impl FromStr for GoTo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (line, col) = match s.split_once(':') {
+            Some((line, col)) => (line, Some(col)),
+            None => (s, None),
+        };
+
+        let line = if let Some(lines) = line.strip_prefix('+') {
+            LineTarget::Relative(parse_number(lines)? as isize)
+        } else if let Some(lines) = line.strip_prefix('-') {
+            LineTarget::Relative(-(parse_number(lines)? as isize))
+        } else {
+            LineTarget::Absolute(parse_number(line)?)
+        };
+
+        let col = match col {
+            Some(col) => Some(parse_number(col)?),
+            None => None,
+        };
+
+        Ok(Self { line, col })
+    }
+}
+
+impl GoTo {
+    /// Turns the target into a 0-based coordinate in `rows`, starting from `current_line`.
+    /// Fails if the line is outside of the file, the column is clamped to the end of the line.
+    pub fn resolve(
+        &self,
+        current_line: usize,
+        rows: &[String],
+    ) -> Result<Coordinates<usize>, String> {
+        let line = match self.line {
+            LineTarget::Absolute(line) => line.checked_sub(1),
+            LineTarget::Relative(lines) => current_line.checked_add_signed(lines),
+        };
+
+        let y = match line {
+            Some(y) if y < rows.len() => y,
+            _ => {
+                return Err(format!(
+                    "Line out of range, the file has {} lines",
+                    rows.len()
+                ))
+            }
+        };
+
+        let x = self
+            .col
+            .map_or(0, |col| col.saturating_sub(1))
+            .min(rows[y].len());
+
+        Ok(Coordinates::new(x, y))
+    }
+}
+
+fn parse_number(s: &str) -> Result<usize, String> {
+    s.parse().map_err(|_| format!("Invalid number: \"{}\"", s))
+}
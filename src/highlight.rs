@@ -0,0 +1,105 @@
+use crate::filetype::FileType;
+
+/// Kind of text, each one drawn with its own style of the theme.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TokenClass {
+    Normal,
+    Keyword,
+    Type,
+    String,
+    Number,
+    Comment,
+}
+
+/// Class of every byte of the first `until` rows. Block comments can span lines,
+/// so the rows before the visible ones are needed too.
+pub fn highlight(rows: &[String], filetype: &FileType, until: usize) -> Vec<Vec<TokenClass>> {
+    let mut in_comment = false;
+    rows.iter()
+        .take(until)
+        .map(|row| highlight_row(row, filetype, &mut in_comment))
+        .collect()
+}
+
+fn highlight_row(row: &str, filetype: &FileType, in_comment: &mut bool) -> Vec<TokenClass> {
+    let mut classes = vec![TokenClass::Normal; row.len()];
+    let mut x = 0;
+
+    while x < row.len() {
+        let rest = &row[x..];
+
+        if *in_comment {
+            let (_, end) = filetype.block_comment.unwrap_or_default();
+            let len = match rest.find(end) {
+                Some(i) => {
+                    *in_comment = false;
+                    i + end.len()
+                }
+                None => rest.len(),
+            };
+            classes[x..x + len].fill(TokenClass::Comment);
+            x += len;
+            continue;
+        }
+        if let Some((start, _)) = filetype.block_comment {
+            if rest.starts_with(start) {
+                *in_comment = true;
+                classes[x..x + start.len()].fill(TokenClass::Comment);
+                x += start.len();
+                continue;
+            }
+        }
+        if filetype
+            .line_comment
+            .is_some_and(|comment| rest.starts_with(comment))
+        {
+            classes[x..].fill(TokenClass::Comment);
+            break;
+        }
+
+        let Some(ch) = rest.chars().next() else {
+            break;
+        };
+        let len = if filetype.quotes.contains(&ch) {
+            let len = string_len(rest, ch);
+            classes[x..x + len].fill(TokenClass::String);
+            len
+        } else if ch.is_alphanumeric() || ch == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            let class = if ch.is_ascii_digit() {
+                TokenClass::Number
+            } else if filetype.keywords.contains(&word) {
+                TokenClass::Keyword
+            } else if filetype.types.contains(&word) {
+                TokenClass::Type
+            } else {
+                TokenClass::Normal
+            };
+            classes[x..x + len].fill(class);
+            len
+        } else {
+            ch.len_utf8()
+        };
+        x += len;
+    }
+    classes
+}
+
+/// Length of the string starting at `text`, up to the closing quote or the end
+/// of the line.
+fn string_len(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, ch) in text.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if ch == '\\' {
+            escaped = true;
+        } else if ch == quote {
+            return i + ch.len_utf8();
+        }
+    }
+    text.len()
+}
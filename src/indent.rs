@@ -0,0 +1,144 @@
+use std::fmt::{Display, Formatter};
+
+/// What one level of indentation is made of in a buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces(usize),
+}
+
+impl Default for IndentStyle {
+    fn default() -> Self {
+        IndentStyle::Spaces(4)
+    }
+}
+
+impl IndentStyle {
+    pub fn unit(&self) -> String {
+        match self {
+            IndentStyle::Tabs => "\t".to_string(),
+            IndentStyle::Spaces(width) => " ".repeat(*width),
+        }
+    }
+}
+
+impl Display for IndentStyle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IndentStyle::Tabs => write!(f, "Tabs"),
+            IndentStyle::Spaces(width) => write!(f, "Spaces:{}", width),
+        }
+    }
+}
+
+/// Guesses how `rows` are indented. Tabs when most indented lines start with one,
+/// otherwise the most common increase of indentation between a line and the next
+/// one, like `detectindent` does. `None` when nothing is indented.
+pub fn detect(rows: &[String]) -> Option<IndentStyle> {
+    let mut tabs = 0;
+    let mut spaces = 0;
+    let mut steps = [0; 9];
+    let mut previous = 0;
+    for row in rows.iter().filter(|row| !row.trim().is_empty()) {
+        let whitespace = leading_whitespace(row);
+        if whitespace.starts_with('\t') {
+            tabs += 1;
+            continue;
+        }
+        if whitespace.contains('\t') {
+            continue;
+        }
+        let width = whitespace.len();
+        if width > 0 {
+            spaces += 1;
+        }
+        // steps of one are usually the continuation of a block comment
+        if (2..steps.len()).contains(&(width.saturating_sub(previous))) {
+            steps[width - previous] += 1;
+        }
+        previous = width;
+    }
+
+    if tabs > spaces {
+        return Some(IndentStyle::Tabs);
+    }
+    // on a tie the narrowest width wins
+    let width = (2..steps.len()).rev().max_by_key(|&width| steps[width])?;
+    (steps[width] > 0).then_some(IndentStyle::Spaces(width))
+}
+
+pub fn leading_whitespace(row: &str) -> &str {
+    &row[..row.len() - row.trim_start().len()]
+}
+
+/// `whitespace` with one level of indentation less: the last tab, or up to a
+/// level worth of spaces, a tab's width of them when indenting with tabs.
+pub fn outdent(whitespace: &str, style: IndentStyle, tab_width: usize) -> &str {
+    if let Some(rest) = whitespace.strip_suffix('\t') {
+        return rest;
+    }
+    let width = match style {
+        IndentStyle::Spaces(width) => width,
+        IndentStyle::Tabs => tab_width,
+    };
+    let spaces = whitespace.len() - whitespace.trim_end_matches(' ').len();
+    &whitespace[..whitespace.len() - spaces.min(width)]
+}
+
+/// Bracket closing `open`, if it is one of `(`, `[` and `{`.
+pub fn closing_bracket(open: char) -> Option<char> {
+    match open {
+        '(' => Some(')'),
+        '[' => Some(']'),
+        '{' => Some('}'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(text: &str) -> Vec<String> {
+        text.lines().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn detects_spaces() {
+        let text = "fn main() {\n  if x {\n    y();\n  }\n\n  z();\n}\n";
+        assert_eq!(detect(&rows(text)), Some(IndentStyle::Spaces(2)));
+        let text = "a:\n    b\n        c\n    d\n";
+        assert_eq!(detect(&rows(text)), Some(IndentStyle::Spaces(4)));
+    }
+
+    #[test]
+    fn detects_tabs() {
+        let text = "a {\n\tb\n\t\tc\n   * comment\n}\n";
+        assert_eq!(detect(&rows(text)), Some(IndentStyle::Tabs));
+    }
+
+    #[test]
+    fn ignores_comment_continuations() {
+        let text = "/**\n * doc\n */\nfn f() {\n    g();\n}\n";
+        assert_eq!(detect(&rows(text)), Some(IndentStyle::Spaces(4)));
+        assert_eq!(detect(&rows("/*\n * a\n */\n")), None);
+    }
+
+    #[test]
+    fn detects_nothing() {
+        assert_eq!(detect(&rows("a\nb\n\n")), None);
+        assert_eq!(detect(&[]), None);
+    }
+
+    #[test]
+    fn outdents() {
+        assert_eq!(outdent("\t\t", IndentStyle::Tabs, 8), "\t");
+        assert_eq!(outdent("        ", IndentStyle::Spaces(4), 8), "    ");
+        assert_eq!(outdent("      ", IndentStyle::Spaces(4), 8), "  ");
+        assert_eq!(outdent("  ", IndentStyle::Spaces(4), 8), "");
+        assert_eq!(outdent("\t  ", IndentStyle::Spaces(4), 8), "\t");
+        assert_eq!(outdent("  ", IndentStyle::Tabs, 8), "");
+        assert_eq!(outdent("      ", IndentStyle::Tabs, 4), "  ");
+        assert_eq!(outdent("", IndentStyle::Spaces(4), 8), "");
+    }
+}
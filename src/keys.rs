@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::commands::Command;
+use crate::config::config_file;
+
+type Key = (KeyCode, KeyModifiers);
+
+const KEY_NAMES: [(&str, KeyCode); 14] = [
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Enter", KeyCode::Enter),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("Delete", KeyCode::Delete),
+    ("Insert", KeyCode::Insert),
+    ("Esc", KeyCode::Esc),
+];
+
+const DEFAULT_BINDINGS: [(&str, Command); 49] = [
+    ("ctrl-q", Command::Quit),
+    ("ctrl-s", Command::Save),
+    ("ctrl-f", Command::Find),
+    ("ctrl-o", Command::Open),
+    ("ctrl-g", Command::GoTo),
+    ("alt-r", Command::ToggleReadOnly),
+    ("ctrl-pagedown", Command::NextFile),
+    ("ctrl-pageup", Command::PreviousFile),
+    ("left", Command::MoveLeft),
+    ("right", Command::MoveRight),
+    ("up", Command::MoveUp),
+    ("down", Command::MoveDown),
+    ("ctrl-left", Command::MoveWordLeft),
+    ("ctrl-right", Command::MoveWordRight),
+    ("home", Command::MoveLineStart),
+    ("end", Command::MoveLineEnd),
+    ("pageup", Command::MovePageUp),
+    ("pagedown", Command::MovePageDown),
+    ("ctrl-home", Command::MoveFileStart),
+    ("ctrl-end", Command::MoveFileEnd),
+    ("ctrl-]", Command::MatchingBracket),
+    // what Ctrl-] is read as without the keyboard enhancements
+    ("ctrl-5", Command::MatchingBracket),
+    ("enter", Command::Newline),
+    ("backspace", Command::Backspace),
+    ("delete", Command::Delete),
+    ("ctrl-backspace", Command::DeleteWordLeft),
+    ("alt-backspace", Command::DeleteWordLeft),
+    ("ctrl-delete", Command::DeleteWordRight),
+    ("ctrl-/", Command::ToggleComment),
+    ("ctrl-7", Command::ToggleComment),
+    ("alt-up", Command::MoveLinesUp),
+    ("alt-down", Command::MoveLinesDown),
+    ("ctrl-d", Command::DuplicateLines),
+    ("ctrl-k", Command::DeleteLines),
+    ("ctrl-j", Command::JoinLines),
+    ("tab", Command::Indent),
+    ("shift-tab", Command::Outdent),
+    ("alt-d", Command::AddCursorAtNext),
+    ("ctrl-alt-up", Command::AddCursorAbove),
+    ("ctrl-alt-down", Command::AddCursorBelow),
+    ("esc", Command::ClearCursors),
+    ("alt-shift-left", Command::BlockLeft),
+    ("alt-shift-right", Command::BlockRight),
+    ("alt-shift-up", Command::BlockUp),
+    ("alt-shift-down", Command::BlockDown),
+    ("ctrl-c", Command::Copy),
+    ("ctrl-x", Command::Cut),
+    ("ctrl-v", Command::Paste),
+    ("ctrl-p", Command::Prompt),
+];
+
+/// Map from keys to the commands they run. Starts with the default bindings,
+/// `keys.toml` in the configuration directory can change them.
+pub struct KeyBindings {
+    map: HashMap<Key, Command>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let map = DEFAULT_BINDINGS
+            .iter()
+            .map(|(name, command)| (parse_key(name).unwrap(), *command))
+            .collect();
+        Self { map }
+    }
+}
+
+impl KeyBindings {
+    /// Default bindings changed by `keys.toml`, with the problems found in the file.
+    pub fn load() -> (Self, Vec<String>) {
+        let mut bindings = Self::default();
+        let Some(path) = config_file("keys.toml") else {
+            return (bindings, vec![]);
+        };
+        let warnings = match fs::read_to_string(&path) {
+            Ok(text) => bindings.apply(&text),
+            Err(err) if err.kind() == ErrorKind::NotFound => vec![],
+            Err(err) => vec![format!("can't read {}: {}", path.display(), err)],
+        };
+        (bindings, warnings)
+    }
+
+    /// Applies bindings written as `"ctrl-s" = "save"`, `"none"` removes the
+    /// binding of a key. Returns the entries that could not be used.
+    pub fn apply(&mut self, text: &str) -> Vec<String> {
+        let table = match text.parse::<toml::Table>() {
+            Ok(table) => table,
+            Err(err) => return vec![err.message().to_string()],
+        };
+
+        let mut warnings = vec![];
+        let mut seen: HashMap<Key, &str> = HashMap::new();
+        for (name, value) in table.iter() {
+            let key = match parse_key(name) {
+                Ok(key) => key,
+                Err(err) => {
+                    warnings.push(err);
+                    continue;
+                }
+            };
+            if let Some(other) = seen.insert(key, name) {
+                warnings.push(format!("'{}' and '{}' are the same key", other, name));
+                continue;
+            }
+
+            match value.as_str() {
+                Some("none") => {
+                    self.map.remove(&key);
+                }
+                Some(action) => match action.parse::<Command>() {
+                    Ok(command) => {
+                        self.map.insert(key, command);
+                    }
+                    Err(err) => warnings.push(format!("{} for '{}'", err, name)),
+                },
+                None => warnings.push(format!("the action for '{}' must be a string", name)),
+            }
+        }
+        warnings
+    }
+
+    /// Command bound to `key`. Keys other than characters that are not bound with
+    /// their modifiers fall back to the unmodified key, so Shift-Left moves left.
+    pub fn get(&self, key: KeyEvent) -> Option<Command> {
+        let key = normalize(key.code, key.modifiers);
+        self.map.get(&key).copied().or_else(|| match key.0 {
+            KeyCode::Char(_) => None,
+            code => self.map.get(&(code, KeyModifiers::NONE)).copied(),
+        })
+    }
+
+    /// Name of a key that runs `command`, like `Ctrl-S`. The first default key
+    /// still bound to it comes before the keys added by `keys.toml`.
+    pub fn key_for(&self, command: Command) -> Option<String> {
+        let default = DEFAULT_BINDINGS
+            .iter()
+            .map(|(name, _)| parse_key(name).unwrap())
+            .find(|key| self.map.get(key) == Some(&command));
+        default.map(key_name).or_else(|| {
+            self.map
+                .iter()
+                .filter(|(_, bound)| **bound == command)
+                .map(|(key, _)| key_name(*key))
+                .min_by_key(|name| (name.len(), name.clone()))
+        })
+    }
+}
+
+/// Parses key names like `ctrl-s`, `alt-shift-up` or `f5`, ignoring case.
+pub fn parse_key(name: &str) -> Result<Key, String> {
+    let invalid = || format!("invalid key '{}'", name);
+
+    let (modifier_names, key) = match name.strip_suffix("--") {
+        Some(modifier_names) => (modifier_names, "-"),
+        None => name.rsplit_once('-').unwrap_or(("", name)),
+    };
+
+    let mut modifiers = KeyModifiers::NONE;
+    for modifier in modifier_names.split('-').filter(|m| !m.is_empty()) {
+        modifiers |= match modifier.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" | "meta" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return Err(invalid()),
+        };
+    }
+
+    let mut chars = key.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(ch), None) => KeyCode::Char(ch),
+        _ if key.eq_ignore_ascii_case("space") => KeyCode::Char(' '),
+        _ => match KEY_NAMES
+            .iter()
+            .find(|(key_name, _)| key_name.eq_ignore_ascii_case(key))
+        {
+            Some((_, code)) => *code,
+            None => match key.to_ascii_lowercase().strip_prefix('f') {
+                Some(n) => KeyCode::F(
+                    n.parse()
+                        .ok()
+                        .filter(|n| (1..=12).contains(n))
+                        .ok_or_else(invalid)?,
+                ),
+                None => return Err(invalid()),
+            },
+        },
+    };
+    Ok(normalize(code, modifiers))
+}
+
+/// Terminals send Ctrl with a letter as the lower case letter and Shift as the
+/// upper case one, so Shift is only kept for the other keys.
+fn normalize(code: KeyCode, modifiers: KeyModifiers) -> Key {
+    let modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+    match code {
+        KeyCode::Char(ch) if modifiers.contains(KeyModifiers::CONTROL) => (
+            KeyCode::Char(ch.to_ascii_lowercase()),
+            modifiers - KeyModifiers::SHIFT,
+        ),
+        KeyCode::Char(ch) if modifiers.contains(KeyModifiers::SHIFT) => (
+            KeyCode::Char(ch.to_ascii_uppercase()),
+            modifiers - KeyModifiers::SHIFT,
+        ),
+        KeyCode::BackTab => (KeyCode::Tab, modifiers | KeyModifiers::SHIFT),
+        code => (code, modifiers),
+    }
+}
+
+fn key_name((code, modifiers): Key) -> String {
+    let mut name = String::new();
+    if modifiers.contains(KeyModifiers::CONTROL) {
+        name += "Ctrl-";
+    }
+    if modifiers.contains(KeyModifiers::ALT) {
+        name += "Alt-";
+    }
+    if modifiers.contains(KeyModifiers::SHIFT) {
+        name += "Shift-";
+    }
+    match code {
+        KeyCode::Char(' ') => name += "Space",
+        KeyCode::Char(ch) if modifiers.is_empty() => name.push(ch),
+        KeyCode::Char(ch) => name.push(ch.to_ascii_uppercase()),
+        KeyCode::F(n) => name += &format!("F{}", n),
+        code => match KEY_NAMES.iter().find(|(_, key_code)| *key_code == code) {
+            Some((key_name, _)) => name += key_name,
+            None => name += &format!("{:?}", code),
+        },
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(
+            parse_key("ctrl-s"),
+            Ok((KeyCode::Char('s'), KeyModifiers::CONTROL))
+        );
+        assert_eq!(
+            parse_key("Ctrl-Shift-S"),
+            Ok((KeyCode::Char('s'), KeyModifiers::CONTROL))
+        );
+        assert_eq!(
+            parse_key("shift-a"),
+            Ok((KeyCode::Char('A'), KeyModifiers::NONE))
+        );
+        assert_eq!(
+            parse_key("alt-shift-up"),
+            Ok((KeyCode::Up, KeyModifiers::ALT | KeyModifiers::SHIFT))
+        );
+        assert_eq!(
+            parse_key("meta-pagedown"),
+            Ok((KeyCode::PageDown, KeyModifiers::ALT))
+        );
+        assert_eq!(
+            parse_key("ctrl--"),
+            Ok((KeyCode::Char('-'), KeyModifiers::CONTROL))
+        );
+        assert_eq!(
+            parse_key("space"),
+            Ok((KeyCode::Char(' '), KeyModifiers::NONE))
+        );
+        assert_eq!(parse_key("F12"), Ok((KeyCode::F(12), KeyModifiers::NONE)));
+        assert_eq!(
+            parse_key("shift-tab"),
+            Ok((KeyCode::Tab, KeyModifiers::SHIFT))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        for name in ["", "ctrl-", "ctrl-zz", "hyper-a", "f0", "f13", "fx"] {
+            assert_eq!(parse_key(name), Err(format!("invalid key '{}'", name)));
+        }
+    }
+
+    #[test]
+    fn applies_bindings() {
+        let mut bindings = KeyBindings::default();
+        let warnings =
+            bindings.apply("\"ctrl-w\" = \"quit\"\n\"ctrl-q\" = \"none\"\n\"ctrl-x\" = \"nope\"\n");
+        assert_eq!(warnings.len(), 1);
+        let key = |code, modifiers| KeyEvent::new(code, modifiers);
+        assert_eq!(
+            bindings.get(key(KeyCode::Char('w'), KeyModifiers::CONTROL)),
+            Some(Command::Quit)
+        );
+        assert_eq!(
+            bindings.get(key(KeyCode::Char('q'), KeyModifiers::CONTROL)),
+            None
+        );
+        assert_eq!(
+            bindings.get(key(KeyCode::Left, KeyModifiers::SHIFT)),
+            Some(Command::MoveLeft)
+        );
+        assert_eq!(
+            bindings.get(key(KeyCode::Char('h'), KeyModifiers::CONTROL)),
+            None
+        );
+        assert_eq!(
+            KeyBindings::default().apply("\"ctrl-a\" = 1\n"),
+            vec!["the action for 'ctrl-a' must be a string".to_string()]
+        );
+        assert_eq!(
+            KeyBindings::default().apply("\"ctrl-a\" = \"quit\"\n\"Ctrl-A\" = \"save\"\n"),
+            vec!["'Ctrl-A' and 'ctrl-a' are the same key".to_string()]
+        );
+    }
+
+    #[test]
+    fn names_the_first_default_key() {
+        let mut bindings = KeyBindings::default();
+        assert_eq!(
+            bindings.key_for(Command::MatchingBracket).as_deref(),
+            Some("Ctrl-]")
+        );
+        assert_eq!(bindings.key_for(Command::Quit).as_deref(), Some("Ctrl-Q"));
+        bindings.apply("\"ctrl-q\" = \"none\"\n\"alt-f4\" = \"quit\"\n");
+        assert_eq!(bindings.key_for(Command::Quit).as_deref(), Some("Alt-F4"));
+    }
+}
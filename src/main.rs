@@ -1,3 +1,5 @@
+use std::env;
+
 mod errors;
 use errors::IoError;
 
@@ -7,11 +9,48 @@ use editor::*;
 mod screen;
 use screen::*;
 
+mod block;
+mod brackets;
+mod cli;
+mod commands;
+mod comment;
+mod config;
 mod coords;
+mod cursors;
 mod directions;
+mod editorconfig;
+mod emacs;
+mod ex;
+mod filetype;
+mod finder;
+mod goto;
+mod highlight;
+mod indent;
+mod keys;
+mod prompt;
+mod theme;
+mod vi;
+mod words;
 
 fn main() {
-    let mut editor = Editor::new();
+    let args = match cli::parse(env::args().skip(1)) {
+        Ok(cli::Command::Edit(args)) => args,
+        Ok(cli::Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Ok(cli::Command::Version) => {
+            println!("texty {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(err) => {
+            eprintln!("texty: {err}");
+            eprintln!("Try 'texty --help' for more information.");
+            std::process::exit(2);
+        }
+    };
+
+    let mut editor = Editor::new(args);
 
     editor.run();
 }
@@ -0,0 +1,249 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::coords::Coordinates;
+use crate::directions::Direction;
+use crate::words::word_boundary;
+
+/// What a key did to the prompt.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PromptEvent {
+    Changed,
+    Moved,
+    Submit,
+    Cancel,
+    /// Tab, left to the caller for completion
+    Complete,
+    Ignored,
+}
+
+/// Line of text typed in the status bar, with a cursor and the history of the
+/// lines entered before in the same kind of prompt.
+#[derive(Default)]
+pub struct Prompt {
+    input: String,
+    cursor: usize,
+    history_index: Option<usize>,
+    draft: String,
+}
+
+impl Prompt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Cursor position in characters.
+    pub fn cursor(&self) -> usize {
+        self.input[..self.cursor].chars().count()
+    }
+
+    pub fn set_input(&mut self, input: impl Into<String>) {
+        self.input = input.into();
+        self.cursor = self.input.len();
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent, history: &[String]) -> PromptEvent {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+
+        match key.code {
+            KeyCode::Enter => PromptEvent::Submit,
+            KeyCode::Esc => PromptEvent::Cancel,
+            KeyCode::Tab => PromptEvent::Complete,
+            KeyCode::Char('c' | 'g') if ctrl => PromptEvent::Cancel,
+            KeyCode::Char('a') if ctrl => self.move_to(0),
+            KeyCode::Char('e') if ctrl => self.move_to(self.input.len()),
+            KeyCode::Char('w') if ctrl => self.delete_to(self.word_boundary(Direction::Left)),
+            KeyCode::Char('u') if ctrl => self.delete_to(0),
+            KeyCode::Char(ch) if !ctrl && !alt => {
+                self.input.insert(self.cursor, ch);
+                self.cursor += ch.len_utf8();
+                PromptEvent::Changed
+            }
+            KeyCode::Backspace if ctrl || alt => {
+                self.delete_to(self.word_boundary(Direction::Left))
+            }
+            KeyCode::Backspace => self.delete_to(self.step(Direction::Left)),
+            KeyCode::Delete if ctrl => self.delete_to(self.word_boundary(Direction::Right)),
+            KeyCode::Delete => self.delete_to(self.step(Direction::Right)),
+            KeyCode::Left if ctrl => self.move_to(self.word_boundary(Direction::Left)),
+            KeyCode::Right if ctrl => self.move_to(self.word_boundary(Direction::Right)),
+            KeyCode::Left => self.move_to(self.step(Direction::Left)),
+            KeyCode::Right => self.move_to(self.step(Direction::Right)),
+            KeyCode::Home => self.move_to(0),
+            KeyCode::End => self.move_to(self.input.len()),
+            KeyCode::Up => self.browse_history(history, true),
+            KeyCode::Down => self.browse_history(history, false),
+            _ => PromptEvent::Ignored,
+        }
+    }
+
+    fn move_to(&mut self, cursor: usize) -> PromptEvent {
+        self.cursor = cursor;
+        PromptEvent::Moved
+    }
+
+    /// Deletes between the cursor and `to`.
+    fn delete_to(&mut self, to: usize) -> PromptEvent {
+        if to == self.cursor {
+            return PromptEvent::Ignored;
+        }
+        let start = to.min(self.cursor);
+        let end = to.max(self.cursor);
+        self.input.replace_range(start..end, "");
+        self.cursor = start;
+        PromptEvent::Changed
+    }
+
+    fn step(&self, direction: Direction) -> usize {
+        match direction {
+            Direction::Left => self.input[..self.cursor]
+                .chars()
+                .next_back()
+                .map_or(self.cursor, |ch| self.cursor - ch.len_utf8()),
+            _ => self.input[self.cursor..]
+                .chars()
+                .next()
+                .map_or(self.cursor, |ch| self.cursor + ch.len_utf8()),
+        }
+    }
+
+    fn word_boundary(&self, direction: Direction) -> usize {
+        let rows = [self.input.clone()];
+        word_boundary(&rows, Coordinates::new(self.cursor, 0), direction).x()
+    }
+
+    /// Up goes to older entries, Down to newer ones and back to what was being typed.
+    fn browse_history(&mut self, history: &[String], older: bool) -> PromptEvent {
+        let index = match (self.history_index, older) {
+            (None, true) if !history.is_empty() => {
+                self.draft = self.input.clone();
+                Some(history.len() - 1)
+            }
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index + 1 < history.len() => Some(index + 1),
+            (Some(_), false) => None,
+            _ => return PromptEvent::Ignored,
+        };
+
+        self.history_index = index;
+        let input = match index {
+            Some(index) => history[index].clone(),
+            None => self.draft.clone(),
+        };
+        self.set_input(input);
+        PromptEvent::Changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn ctrl(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::CONTROL)
+    }
+
+    fn type_text(prompt: &mut Prompt, text: &str) {
+        for ch in text.chars() {
+            assert_eq!(
+                prompt.handle_key(key(KeyCode::Char(ch)), &[]),
+                PromptEvent::Changed
+            );
+        }
+    }
+
+    #[test]
+    fn editing() {
+        let mut prompt = Prompt::new();
+        type_text(&mut prompt, "héllo");
+        assert_eq!(prompt.cursor(), 5);
+        prompt.handle_key(key(KeyCode::Left), &[]);
+        prompt.handle_key(key(KeyCode::Left), &[]);
+        prompt.handle_key(key(KeyCode::Left), &[]);
+        assert_eq!(prompt.cursor(), 2);
+        prompt.handle_key(key(KeyCode::Backspace), &[]);
+        assert_eq!(prompt.input(), "hllo");
+        prompt.handle_key(key(KeyCode::Delete), &[]);
+        assert_eq!(prompt.input(), "hlo");
+        prompt.handle_key(ctrl(KeyCode::Char('e')), &[]);
+        type_text(&mut prompt, " world");
+        assert_eq!(prompt.input(), "hlo world");
+        assert_eq!(
+            prompt.handle_key(ctrl(KeyCode::Char('w')), &[]),
+            PromptEvent::Changed
+        );
+        assert_eq!(prompt.input(), "hlo ");
+        prompt.handle_key(ctrl(KeyCode::Char('u')), &[]);
+        assert_eq!(prompt.input(), "");
+        assert_eq!(
+            prompt.handle_key(key(KeyCode::Backspace), &[]),
+            PromptEvent::Ignored
+        );
+    }
+
+    #[test]
+    fn words() {
+        let mut prompt = Prompt::new();
+        type_text(&mut prompt, "one two");
+        prompt.handle_key(ctrl(KeyCode::Left), &[]);
+        assert_eq!(prompt.cursor(), 4);
+        prompt.handle_key(ctrl(KeyCode::Delete), &[]);
+        assert_eq!(prompt.input(), "one ");
+        prompt.handle_key(key(KeyCode::Home), &[]);
+        prompt.handle_key(ctrl(KeyCode::Right), &[]);
+        assert_eq!(prompt.cursor(), 3);
+        assert_eq!(
+            prompt.handle_key(ctrl(KeyCode::Char('h')), &[]),
+            PromptEvent::Ignored
+        );
+    }
+
+    #[test]
+    fn history() {
+        let history = ["first".to_string(), "second".to_string()];
+        let mut prompt = Prompt::new();
+        type_text(&mut prompt, "draft");
+        prompt.handle_key(key(KeyCode::Up), &history);
+        assert_eq!(prompt.input(), "second");
+        prompt.handle_key(key(KeyCode::Up), &history);
+        prompt.handle_key(key(KeyCode::Up), &history);
+        assert_eq!(prompt.input(), "first");
+        prompt.handle_key(key(KeyCode::Down), &history);
+        assert_eq!(prompt.input(), "second");
+        prompt.handle_key(key(KeyCode::Down), &history);
+        assert_eq!(prompt.input(), "draft");
+        assert_eq!(
+            prompt.handle_key(key(KeyCode::Down), &history),
+            PromptEvent::Ignored
+        );
+    }
+
+    #[test]
+    fn submit_and_cancel() {
+        let mut prompt = Prompt::new();
+        assert_eq!(
+            prompt.handle_key(key(KeyCode::Enter), &[]),
+            PromptEvent::Submit
+        );
+        assert_eq!(
+            prompt.handle_key(key(KeyCode::Esc), &[]),
+            PromptEvent::Cancel
+        );
+        assert_eq!(
+            prompt.handle_key(ctrl(KeyCode::Char('g')), &[]),
+            PromptEvent::Cancel
+        );
+        assert_eq!(
+            prompt.handle_key(key(KeyCode::Tab), &[]),
+            PromptEvent::Complete
+        );
+    }
+}
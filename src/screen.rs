@@ -1,5 +1,11 @@
+use crate::block::Block;
+use crate::config::Settings;
 use crate::coords::Coordinates;
+use crate::highlight::TokenClass;
+use crate::indent::IndentStyle;
+use crate::theme::{ColorSupport, Style, Theme};
 use crossterm::cursor;
+use crossterm::event;
 use crossterm::style;
 use crossterm::style::SetAttribute;
 use crossterm::style::SetBackgroundColor;
@@ -9,40 +15,130 @@ use crossterm::QueueableCommand;
 use std::io;
 use std::io::Error;
 use std::io::ErrorKind;
-use std::io::Stdout;
 use std::io::Write;
-use std::time::Duration;
 use std::time::Instant;
 use std::u16;
 
+/// What the status bar shows about the buffer.
+pub struct StatusInfo<'a> {
+    pub file: &'a str,
+    pub changed: bool,
+    pub read_only: bool,
+    pub mode: Option<String>,
+    pub indent: IndentStyle,
+}
+
+/// Things drawn over the text. Positions are in file coordinates, ranges go from
+/// the start (included) to the end (excluded).
+#[derive(Default)]
+pub struct Decorations {
+    pub selection: Option<(Coordinates<usize>, Coordinates<usize>)>,
+    pub matches: Vec<(Coordinates<usize>, Coordinates<usize>)>,
+    /// Class of every byte of the rows, indexed like them. Rows without
+    /// highlighting are missing.
+    pub tokens: Vec<Vec<TokenClass>>,
+    /// Bracket under the cursor and the one matching it, `None` if it is unmatched
+    pub bracket: Option<(Coordinates<usize>, Option<Coordinates<usize>>)>,
+    /// Cursors besides the one of the terminal
+    pub cursors: Vec<Coordinates<usize>>,
+    /// Block selection, in columns of the screen
+    pub block: Option<Block>,
+}
+
 pub struct Screen {
-    stdout: Stdout,
+    output: Box<dyn Write>,
+    /// Columns available for the text, the terminal width without the gutter
     pub width: u16,
     pub height: u16,
+    term_width: u16,
+    gutter: u16,
     row_offset: u16,
     col_offset: u16,
     status_msg: String,
     status_time: Instant,
+    settings: Settings,
+    theme: Theme,
+    color_support: ColorSupport,
 }
 
 impl Screen {
-    pub fn new(stdout: Stdout, width: u16, height: u16) -> Self {
+    pub fn new(output: Box<dyn Write>, width: u16, height: u16) -> Self {
         Self {
-            stdout,
+            output,
             width,
             height: height - 1,
+            term_width: width,
+            gutter: 0,
             row_offset: 0,
             col_offset: 0,
             status_msg: "".to_string(),
             status_time: Instant::now(),
+            settings: Settings::default(),
+            theme: Theme::default(),
+            color_support: ColorSupport::detect(),
+        }
+    }
+
+    pub fn theme_name(&self) -> &str {
+        &self.theme.name
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme.degrade(self.color_support);
+    }
+
+    pub fn apply_settings(&mut self, settings: &Settings) -> io::Result<()> {
+        if settings.mouse != self.settings.mouse {
+            if settings.mouse {
+                self.output.queue(event::EnableMouseCapture)?;
+            } else {
+                self.output.queue(event::DisableMouseCapture)?;
+            }
         }
+        self.settings = settings.clone();
+        Ok(())
+    }
+
+    /// Makes room for the line numbers of a buffer with `lines` lines.
+    pub fn update_gutter(&mut self, lines: usize) {
+        self.gutter = if self.settings.line_numbers {
+            let digits = lines.max(1).ilog10() as u16 + 1;
+            (digits + 1).min(self.term_width / 2)
+        } else {
+            0
+        };
+        self.width = self.term_width - self.gutter;
     }
 
     pub fn set_status_msg(&mut self, msg: impl Into<String>) -> io::Result<()> {
         self.status_msg = msg.into();
         self.status_time = Instant::now();
-        self.stdout
-            .draw_status_msg(self.width, self.height + 1, &self.status_msg)?
+        self.output
+            .draw_status_msg(
+                self.term_width,
+                self.height + 1,
+                &self.status_msg,
+                &self.theme.message,
+            )?
+            .flush()?;
+        Ok(())
+    }
+
+    /// Shows a prompt in the status bar with the cursor at `cursor` characters
+    /// into the input.
+    pub fn draw_prompt(&mut self, label: &str, input: &str, cursor: usize) -> io::Result<()> {
+        self.status_msg = format!("{}{}", label, input);
+        self.status_time = Instant::now();
+        let col = (label.chars().count() + cursor).min(self.term_width.saturating_sub(1) as usize);
+        self.output
+            .draw_status_msg(
+                self.term_width,
+                self.height + 1,
+                &self.status_msg,
+                &self.theme.message,
+            )?
+            .queue(cursor::MoveTo(col as u16, self.height + 1))?
+            .queue(cursor::Show)?
             .flush()?;
         Ok(())
     }
@@ -50,54 +146,91 @@ impl Screen {
     pub fn refresh_screen(
         &mut self,
         cursor: &Coordinates<u16>,
-        rows: &Vec<String>,
-        file: &str,
-        changes: bool,
+        rows: &[String],
+        status: &StatusInfo,
+        decorations: &Decorations,
     ) -> io::Result<()> {
-        let is_new = !changes && file == "[New file]";
-        self.stdout
+        let is_new = !status.changed && status.file == "[New file]";
+        self.update_gutter(rows.len());
+        self.output
             .queue(style::SetAttribute(style::Attribute::NoUnderline))?
             .queue(SetAttribute(style::Attribute::NormalIntensity))?
             .queue(cursor::Hide)?
             .queue(cursor::MoveTo(0, 0))?
             .draw_rows(
-                "My editor -- version 1",
+                &self.settings,
+                &self.theme,
                 self.width,
                 self.height,
                 rows,
                 self.row_offset,
                 self.col_offset,
+                self.gutter,
                 is_new,
+                decorations,
+            )?;
+        if self.status_time.elapsed() < self.settings.message_timeout {
+            self.output.draw_status_msg(
+                self.term_width,
+                self.height + 1,
+                &self.status_msg,
+                &self.theme.message,
             )?;
-        if self.status_time.elapsed() < Duration::new(1, 0) {
-            self.stdout
-                .draw_status_msg(self.width, self.height + 1, &self.status_msg)?;
         } else {
-            let modifier;
-            if changes {
-                modifier = "*";
-            } else {
-                modifier = "";
+            let mut modifier = String::new();
+            if status.changed {
+                modifier += "*";
             }
-            self.stdout.draw_status_bar(
-                self.width,
-                self.height + 1,
-                file,
-                modifier,
+            if status.read_only {
+                modifier += " [RO]";
+            }
+
+            let mut info = format!(
+                "{} | {}:{}",
+                status.indent,
                 cursor.y() + self.row_offset,
-                cursor.x() + self.col_offset,
+                cursor.x() + self.col_offset
+            );
+            if let Some(mode) = &status.mode {
+                info = format!("{} | {}", mode, info);
+            }
+
+            self.output.draw_status_bar(
+                self.term_width,
+                self.height + 1,
+                status.file,
+                &modifier,
+                &info,
+                &self.theme.status_bar,
             )?;
         }
-        self.stdout
-            .queue(cursor::MoveTo(cursor.x(), cursor.y()))?
+        let cursor_x = match decorations.block {
+            // the corner of a block can be past the end of the row
+            Some(block) => (block.corner.x().saturating_sub(self.col_offset as usize))
+                .min(self.width.saturating_sub(1) as usize) as u16,
+            None => cursor.x(),
+        };
+        self.output
+            .queue(cursor::MoveTo(self.gutter + cursor_x, cursor.y()))?
             .queue(cursor::Show)?
             .flush()?;
 
         Ok(())
     }
 
+    pub fn draw_popup(&mut self, lines: &[String], selected: usize) -> io::Result<()> {
+        let lines = &lines[..lines.len().min(self.height as usize)];
+        let top = self.height - lines.len() as u16;
+
+        self.output
+            .queue(cursor::Hide)?
+            .draw_popup(self.term_width, top, lines, selected)?
+            .flush()?;
+        Ok(())
+    }
+
     pub fn clear_screen(&mut self) -> io::Result<()> {
-        self.stdout
+        self.output
             .queue(terminal::Clear(terminal::ClearType::All))?
             .queue(cursor::MoveTo(0, 0))?
             .flush()?;
@@ -106,7 +239,11 @@ impl Screen {
 
     pub fn reset_screen(&mut self) -> io::Result<()> {
         self.clear_screen()?;
-        self.stdout.queue(cursor::Show)?.flush()?;
+        self.output
+            .queue(cursor::Show)?
+            .queue(event::DisableMouseCapture)?
+            .queue(event::DisableBracketedPaste)?
+            .flush()?;
         Ok(())
     }
 
@@ -137,21 +274,29 @@ impl Screen {
         self.col_offset
     }
 
+    pub fn get_gutter(&self) -> u16 {
+        self.gutter
+    }
+
     pub fn get_row_offset(&self) -> u16 {
         self.row_offset
     }
 }
 
 trait DrawHelper {
+    #[allow(clippy::too_many_arguments)]
     fn draw_rows(
         &mut self,
-        greeting: impl Into<String>,
+        settings: &Settings,
+        theme: &Theme,
         width: u16,
         height: u16,
-        rows: &Vec<String>,
+        rows: &[String],
         offset: u16,
         col_offset: u16,
+        gutter: u16,
         is_new: bool,
+        decorations: &Decorations,
     ) -> io::Result<&mut Self>;
 
     fn draw_status_bar(
@@ -160,63 +305,204 @@ trait DrawHelper {
         height: u16,
         filename: &str,
         modifier: &str,
-        row_num: u16,
-        col_num: u16,
+        info: &str,
+        style: &Style,
     ) -> io::Result<&mut Self>;
 
-    fn draw_status_msg(&mut self, width: u16, height: u16, msg: &str) -> io::Result<&mut Self>;
+    fn draw_status_msg(
+        &mut self,
+        width: u16,
+        height: u16,
+        msg: &str,
+        style: &Style,
+    ) -> io::Result<&mut Self>;
+
+    fn set_style(&mut self, style: &Style) -> io::Result<&mut Self>;
+
+    fn draw_popup(
+        &mut self,
+        width: u16,
+        top: u16,
+        lines: &[String],
+        selected: usize,
+    ) -> io::Result<&mut Self>;
 }
 
-impl DrawHelper for Stdout {
+impl<W: Write> DrawHelper for W {
     fn draw_rows(
         &mut self,
-        greeting: impl Into<String>,
+        settings: &Settings,
+        theme: &Theme,
         width: u16,
         height: u16,
-        rows: &Vec<String>,
+        rows: &[String],
         row_offset: u16,
         col_offset: u16,
+        gutter: u16,
         is_new: bool,
+        decorations: &Decorations,
     ) -> io::Result<&mut Self> {
-        let greeting = greeting.into();
+        let greeting = &settings.greeting;
 
-        let greeting_len: u16 = greeting.len().try_into().unwrap();
         for y in 0..(height) {
             if ((y + row_offset) as usize) < rows.len() {
                 let row_offset = (y + row_offset) as usize;
 
-                let row: String = match rows.iter().nth(row_offset) {
-                    Some(row) => row.clone(),
+                let row: &str = match rows.get(row_offset) {
+                    Some(row) => row,
                     None => {
                         return Err(Error::new(ErrorKind::InvalidInput, "index out of bounds"));
                     }
                 };
 
-                let win_begin = row.len().min(col_offset as usize);
-                let win_end = row.len().min((width + col_offset) as usize);
-                let windowed_row = &row[win_begin..win_end];
+                self.queue(cursor::MoveTo(0, y))?;
+                if gutter > 0 {
+                    self.set_style(&theme.gutter.over(theme.text))?
+                        .queue(style::Print(format!(
+                            "{:>width$} ",
+                            row_offset + 1,
+                            width = gutter as usize - 1
+                        )))?;
+                }
 
-                self.queue(cursor::MoveTo(0, y))?
-                    .queue(style::Print(windowed_row))?
+                let inside = |x: usize, (start, end): (Coordinates<usize>, Coordinates<usize>)| {
+                    (start.y(), start.x()) <= (row_offset, x)
+                        && (row_offset, x) < (end.y(), end.x())
+                };
+                let tokens = decorations.tokens.get(row_offset);
+                let style_at = |x: usize| {
+                    let token = tokens.and_then(|tokens| tokens.get(x));
+                    let mut style = match token {
+                        Some(&class) => theme.token(class).over(theme.text),
+                        None => theme.text,
+                    };
+                    if decorations.matches.iter().any(|&range| inside(x, range)) {
+                        style = theme.search_match.over(style);
+                    }
+                    if decorations.selection.is_some_and(|range| inside(x, range)) {
+                        style = theme.selection.over(style);
+                    }
+                    let here = Coordinates::new(x, row_offset);
+                    match decorations.bracket {
+                        Some((at, Some(other))) if at == here || other == here => {
+                            style = theme.matching_bracket.over(style);
+                        }
+                        Some((at, None)) if at == here => {
+                            style = theme.unmatched_bracket.over(style);
+                        }
+                        _ => (),
+                    }
+                    if decorations.cursors.contains(&here) {
+                        style = theme.cursor.over(style);
+                    }
+                    style
+                };
+
+                let block = decorations
+                    .block
+                    .filter(|block| block.rows().contains(&row_offset))
+                    .map(|block| block.columns());
+
+                // the row is printed in runs of characters drawn the same way
+                let first_col = col_offset as usize;
+                let last_col = first_col + width as usize;
+                let mut col = 0;
+                let mut run = String::new();
+                let mut run_style = theme.text;
+                self.set_style(&run_style)?;
+                let trailing = row.trim_end().len();
+                for (x, ch) in row.char_indices() {
+                    if col >= last_col {
+                        break;
+                    }
+                    let shown = settings.show_whitespace
+                        && (ch == '\t' || ch == '\u{a0}' || (ch == ' ' && x >= trailing));
+                    let (text, cells) = match ch {
+                        '\t' => {
+                            let cells = settings.tab_width - col % settings.tab_width;
+                            if shown {
+                                (format!("→{}", " ".repeat(cells - 1)), cells)
+                            } else {
+                                (" ".repeat(cells), cells)
+                            }
+                        }
+                        _ if shown => ("·".to_string(), 1),
+                        ch => (ch.to_string(), 1),
+                    };
+                    let visible = (col.max(first_col)..(col + cells).min(last_col)).len();
+                    col += cells;
+                    if visible == 0 {
+                        continue;
+                    }
+
+                    let mut style = style_at(x);
+                    if settings.max_line_length > 0 && col - cells >= settings.max_line_length {
+                        style = theme.long_line.over(style);
+                    }
+                    if shown {
+                        style = theme.whitespace.over(style);
+                    }
+                    match &block {
+                        Some(columns) if columns.is_empty() && col - cells == columns.start => {
+                            style = theme.cursor.over(style);
+                        }
+                        Some(columns) if col - cells < columns.end && col > columns.start => {
+                            style = theme.selection.over(style);
+                        }
+                        _ => (),
+                    }
+                    if style != run_style {
+                        self.queue(style::Print(&run))?.set_style(&style)?;
+                        run.clear();
+                        run_style = style;
+                    }
+                    if visible < cells {
+                        run += &" ".repeat(visible);
+                    } else {
+                        run += &text;
+                    }
+                }
+                // the block goes on past the end of short rows
+                if let Some(columns) = &block {
+                    let (end, style) = match columns.is_empty() {
+                        true => (columns.start + 1, theme.cursor),
+                        false => (columns.end, theme.selection),
+                    };
+                    let start = columns.start.max(col);
+                    let cells = (start.max(first_col)..end.min(last_col)).len();
+                    if cells > 0 {
+                        let gap = (col.max(first_col)..start).len();
+                        self.queue(style::Print(&run))?
+                            .set_style(&theme.text)?
+                            .queue(style::Print(" ".repeat(gap)))?
+                            .set_style(&style.over(theme.text))?;
+                        run = " ".repeat(cells);
+                        col = end;
+                    }
+                }
+                // a cursor at the end of the line is drawn on the space after it
+                let end = Coordinates::new(row.len(), row_offset);
+                if decorations.cursors.contains(&end) && (first_col..last_col).contains(&col) {
+                    self.queue(style::Print(&run))?
+                        .set_style(&theme.cursor.over(theme.text))?;
+                    run = " ".to_string();
+                }
+                self.queue(style::Print(&run))?
+                    .set_style(&theme.text)?
                     .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
             } else {
+                self.queue(cursor::MoveTo(0, y))?
+                    .set_style(&theme.gutter.over(theme.text))?
+                    .queue(style::Print("~"))?
+                    .set_style(&theme.text)?;
                 if y == height / 3 && is_new {
-                    let padding: u16 = (width - greeting_len) / 2;
-
-                    self.queue(cursor::MoveTo(0, y))?
-                        .queue(SetAttribute(style::Attribute::Dim))?
-                        .queue(style::Print("~"))?
-                        .queue(SetAttribute(style::Attribute::NormalIntensity))?
-                        .queue(cursor::MoveTo(padding, y))?
-                        .queue(style::Print(greeting.clone()))?
-                        .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
-                } else {
-                    self.queue(cursor::MoveTo(0, y))?
-                        .queue(SetAttribute(style::Attribute::Dim))?
-                        .queue(style::Print("~"))?
-                        .queue(SetAttribute(style::Attribute::NormalIntensity))?
-                        .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
+                    let greeting: String = greeting.chars().take(width as usize).collect();
+                    let padding: u16 = (width - greeting.chars().count() as u16) / 2;
+
+                    self.queue(cursor::MoveTo(gutter + padding, y))?
+                        .queue(style::Print(greeting))?;
                 }
+                self.queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
             }
         }
         Ok(self)
@@ -228,15 +514,10 @@ impl DrawHelper for Stdout {
         height: u16,
         filename: &str,
         modifier: &str,
-        row_num: u16,
-        col_num: u16,
+        info: &str,
+        style: &Style,
     ) -> io::Result<&mut Self> {
-        let location = format!("{}:{}", row_num, col_num);
-
-        self.queue(cursor::MoveTo(0, height))?
-            .queue(SetAttribute(style::Attribute::Bold))?
-            .queue(SetBackgroundColor(style::Color::White))?
-            .queue(SetForegroundColor(style::Color::Black))?;
+        self.queue(cursor::MoveTo(0, height))?.set_style(style)?;
 
         for col in 0..width {
             self.queue(cursor::MoveTo(col, height))?
@@ -248,22 +529,22 @@ impl DrawHelper for Stdout {
             .queue(style::Print(modifier))?;
 
         self.queue(cursor::MoveTo(
-            (width as usize - location.len()).try_into().unwrap(),
+            width.saturating_sub(info.len().try_into().unwrap()),
             height,
         ))?
-        .queue(style::Print(location))?;
+        .queue(style::Print(info))?;
 
-        self.queue(SetAttribute(style::Attribute::NoBold))?
-            .queue(SetForegroundColor(style::Color::White))?
-            .queue(SetBackgroundColor(style::Color::Reset))?;
-        Ok(self)
+        self.set_style(&Style::default())
     }
 
-    fn draw_status_msg(&mut self, width: u16, height: u16, msg: &str) -> io::Result<&mut Self> {
-        self.queue(cursor::MoveTo(0, height))?
-            .queue(SetAttribute(style::Attribute::Bold))?
-            .queue(SetBackgroundColor(style::Color::White))?
-            .queue(SetForegroundColor(style::Color::Black))?;
+    fn draw_status_msg(
+        &mut self,
+        width: u16,
+        height: u16,
+        msg: &str,
+        style: &Style,
+    ) -> io::Result<&mut Self> {
+        self.queue(cursor::MoveTo(0, height))?.set_style(style)?;
 
         for col in 0..width {
             self.queue(cursor::MoveTo(col, height))?
@@ -273,9 +554,70 @@ impl DrawHelper for Stdout {
         self.queue(cursor::MoveTo(0, height))?
             .queue(style::Print(msg))?;
 
-        self.queue(SetAttribute(style::Attribute::NoBold))?
-            .queue(SetForegroundColor(style::Color::White))?
-            .queue(SetBackgroundColor(style::Color::Reset))?;
+        self.set_style(&Style::default())
+    }
+
+    fn set_style(&mut self, style: &Style) -> io::Result<&mut Self> {
+        self.queue(SetAttribute(style::Attribute::Reset))?
+            .queue(SetForegroundColor(style.fg.unwrap_or(style::Color::Reset)))?
+            .queue(SetBackgroundColor(style.bg.unwrap_or(style::Color::Reset)))?;
+        let attributes = [
+            (style.bold, style::Attribute::Bold),
+            (style.dim, style::Attribute::Dim),
+            (style.italic, style::Attribute::Italic),
+            (style.underline, style::Attribute::Underlined),
+            (style.reverse, style::Attribute::Reverse),
+        ];
+        for (_, attribute) in attributes.iter().filter(|(enabled, _)| *enabled) {
+            self.queue(SetAttribute(*attribute))?;
+        }
         Ok(self)
     }
+
+    fn draw_popup(
+        &mut self,
+        width: u16,
+        top: u16,
+        lines: &[String],
+        selected: usize,
+    ) -> io::Result<&mut Self> {
+        for (i, line) in lines.iter().enumerate() {
+            let y = top + i as u16;
+            let line: String = format!(" {}", line).chars().take(width as usize).collect();
+
+            self.queue(cursor::MoveTo(0, y))?;
+            if i == selected {
+                self.queue(SetAttribute(style::Attribute::Reverse))?;
+            }
+            self.queue(style::Print(line))?
+                .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?
+                .queue(SetAttribute(style::Attribute::NoReverse))?;
+        }
+        Ok(self)
+    }
+}
+
+/// Byte index of the character drawn at column `col` of the row, or its end.
+pub fn byte_at_col(row: &str, col: usize, tab_width: usize) -> usize {
+    let mut end = 0;
+    for (x, ch) in row.char_indices() {
+        end = match ch {
+            '\t' => end + tab_width - end % tab_width,
+            _ => end + 1,
+        };
+        if end > col {
+            return x;
+        }
+    }
+    row.len()
+}
+
+/// Column where the character at byte `x` of `row` is drawn.
+pub fn display_col(row: &str, x: usize, tab_width: usize) -> usize {
+    row.char_indices()
+        .take_while(|(i, _)| *i < x)
+        .fold(0, |col, (_, ch)| match ch {
+            '\t' => col + tab_width - col % tab_width,
+            _ => col + 1,
+        })
 }
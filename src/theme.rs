@@ -0,0 +1,436 @@
+use std::env;
+use std::fs;
+
+use crossterm::style::Color;
+use toml::{Table, Value};
+
+use crate::config::config_file;
+use crate::highlight::TokenClass;
+
+/// How a piece of the screen is drawn. Colors left as `None` keep the ones of
+/// the style below, or the terminal's defaults.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    pub const fn fg(color: Color) -> Self {
+        Self {
+            fg: Some(color),
+            bg: None,
+            bold: false,
+            dim: false,
+            italic: false,
+            underline: false,
+            reverse: false,
+        }
+    }
+
+    pub const fn colors(fg: Color, bg: Color) -> Self {
+        Self {
+            bg: Some(bg),
+            ..Self::fg(fg)
+        }
+    }
+
+    const fn bold(self) -> Self {
+        Self { bold: true, ..self }
+    }
+
+    const fn underlined(self) -> Self {
+        Self {
+            underline: true,
+            ..self
+        }
+    }
+
+    /// This style drawn over `base`.
+    pub fn over(self, base: Style) -> Style {
+        Style {
+            fg: self.fg.or(base.fg),
+            bg: self.bg.or(base.bg),
+            bold: self.bold || base.bold,
+            dim: self.dim || base.dim,
+            italic: self.italic || base.italic,
+            underline: self.underline || base.underline,
+            reverse: self.reverse || base.reverse,
+        }
+    }
+
+    fn degrade(self, support: ColorSupport) -> Style {
+        Style {
+            fg: self.fg.map(|color| support.convert(color)),
+            bg: self.bg.map(|color| support.convert(color)),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub text: Style,
+    pub status_bar: Style,
+    pub message: Style,
+    pub selection: Style,
+    pub search_match: Style,
+    pub matching_bracket: Style,
+    pub unmatched_bracket: Style,
+    /// Text beyond `max_line_length`
+    pub long_line: Style,
+    /// Tabs and trailing spaces made visible with `show_whitespace`
+    pub whitespace: Style,
+    /// The cursors other than the terminal's one
+    pub cursor: Style,
+    pub gutter: Style,
+    pub keyword: Style,
+    pub type_name: Style,
+    pub string: Style,
+    pub number: Style,
+    pub comment: Style,
+}
+
+impl Default for Theme {
+    /// Uses the terminal's palette, so it looks right on any background.
+    fn default() -> Self {
+        let status = Style::colors(Color::Black, Color::White).bold();
+        Self {
+            name: "default".to_string(),
+            text: Style::default(),
+            status_bar: status,
+            message: status,
+            selection: Style {
+                reverse: true,
+                ..Style::default()
+            },
+            search_match: Style::colors(Color::Black, Color::DarkYellow),
+            matching_bracket: Style::colors(Color::Black, Color::DarkCyan),
+            unmatched_bracket: Style::colors(Color::White, Color::DarkRed),
+            long_line: Style::fg(Color::DarkRed).underlined(),
+            whitespace: Style::fg(Color::DarkGrey),
+            cursor: Style {
+                reverse: true,
+                ..Style::default()
+            },
+            gutter: Style {
+                dim: true,
+                ..Style::default()
+            },
+            keyword: Style::fg(Color::DarkMagenta),
+            type_name: Style::fg(Color::DarkCyan),
+            string: Style::fg(Color::DarkGreen),
+            number: Style::fg(Color::DarkYellow),
+            comment: Style::fg(Color::DarkGrey),
+        }
+    }
+}
+
+const fn rgb(hex: u32) -> Color {
+    Color::Rgb {
+        r: (hex >> 16) as u8,
+        g: (hex >> 8) as u8,
+        b: hex as u8,
+    }
+}
+
+const MAX_INHERITS: usize = 8;
+
+impl Theme {
+    fn built_in(name: &str) -> Option<Theme> {
+        let theme = match name {
+            "default" => Theme::default(),
+            "dark" => Theme {
+                name: name.to_string(),
+                text: Style::colors(rgb(0xabb2bf), rgb(0x282c34)),
+                status_bar: Style::colors(rgb(0x282c34), rgb(0x61afef)).bold(),
+                message: Style::colors(rgb(0xabb2bf), rgb(0x3e4452)),
+                selection: Style::colors(rgb(0xdcdfe4), rgb(0x3e4451)),
+                search_match: Style::colors(rgb(0x282c34), rgb(0xe5c07b)),
+                matching_bracket: Style::colors(rgb(0x282c34), rgb(0x56b6c2)),
+                unmatched_bracket: Style::colors(rgb(0x282c34), rgb(0xe06c75)),
+                long_line: Style::fg(rgb(0xe06c75)).underlined(),
+                whitespace: Style::fg(rgb(0x4b5263)),
+                cursor: Style::colors(rgb(0x282c34), rgb(0x528bff)),
+                gutter: Style::fg(rgb(0x636d83)),
+                keyword: Style::fg(rgb(0xc678dd)),
+                type_name: Style::fg(rgb(0xe5c07b)),
+                string: Style::fg(rgb(0x98c379)),
+                number: Style::fg(rgb(0xd19a66)),
+                comment: Style {
+                    italic: true,
+                    ..Style::fg(rgb(0x7f848e))
+                },
+            },
+            "light" => Theme {
+                name: name.to_string(),
+                text: Style::colors(rgb(0x383a42), rgb(0xfafafa)),
+                status_bar: Style::colors(rgb(0xfafafa), rgb(0x4078f2)).bold(),
+                message: Style::colors(rgb(0x383a42), rgb(0xe5e5e6)),
+                selection: Style::colors(rgb(0x383a42), rgb(0xd0d0d8)),
+                search_match: Style::colors(rgb(0x383a42), rgb(0xf0d58a)),
+                matching_bracket: Style::colors(rgb(0xfafafa), rgb(0x0184bc)),
+                unmatched_bracket: Style::colors(rgb(0xfafafa), rgb(0xe45649)),
+                long_line: Style::fg(rgb(0xe45649)).underlined(),
+                whitespace: Style::fg(rgb(0xc0c0c6)),
+                cursor: Style::colors(rgb(0xfafafa), rgb(0x526fff)),
+                gutter: Style::fg(rgb(0x9d9d9f)),
+                keyword: Style::fg(rgb(0xa626a4)),
+                type_name: Style::fg(rgb(0xc18401)),
+                string: Style::fg(rgb(0x50a14f)),
+                number: Style::fg(rgb(0x986801)),
+                comment: Style {
+                    italic: true,
+                    ..Style::fg(rgb(0xa0a1a7))
+                },
+            },
+            "solarized" => Theme {
+                name: name.to_string(),
+                text: Style::colors(rgb(0x839496), rgb(0x002b36)),
+                status_bar: Style::colors(rgb(0x002b36), rgb(0x93a1a1)).bold(),
+                message: Style::colors(rgb(0x93a1a1), rgb(0x073642)),
+                selection: Style::colors(rgb(0x93a1a1), rgb(0x073642)),
+                search_match: Style::colors(rgb(0x002b36), rgb(0xb58900)),
+                matching_bracket: Style::colors(rgb(0x002b36), rgb(0x268bd2)),
+                unmatched_bracket: Style::colors(rgb(0x002b36), rgb(0xdc322f)),
+                long_line: Style::fg(rgb(0xdc322f)).underlined(),
+                whitespace: Style::fg(rgb(0x586e75)),
+                cursor: Style::colors(rgb(0x002b36), rgb(0x93a1a1)),
+                gutter: Style::colors(rgb(0x586e75), rgb(0x073642)),
+                keyword: Style::fg(rgb(0x859900)),
+                type_name: Style::fg(rgb(0xb58900)),
+                string: Style::fg(rgb(0x2aa198)),
+                number: Style::fg(rgb(0xd33682)),
+                comment: Style {
+                    italic: true,
+                    ..Style::fg(rgb(0x586e75))
+                },
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// Built-in theme or `themes/NAME.toml` in the configuration directory.
+    pub fn load(name: &str) -> Result<Theme, String> {
+        Theme::load_inherited(name, 0)
+    }
+
+    fn load_inherited(name: &str, depth: usize) -> Result<Theme, String> {
+        if depth > MAX_INHERITS {
+            return Err(format!("theme '{}' inherits from too many themes", name));
+        }
+        if let Some(theme) = Theme::built_in(name) {
+            return Ok(theme);
+        }
+
+        let path = config_file(&format!("themes/{}.toml", name))
+            .ok_or_else(|| format!("unknown theme '{}'", name))?;
+        let text = fs::read_to_string(&path).map_err(|_| format!("unknown theme '{}'", name))?;
+        let table = text
+            .parse::<Table>()
+            .map_err(|err| format!("theme '{}': {}", name, err.message()))?;
+        Theme::from_table(name, &table, depth).map_err(|err| format!("theme '{}': {}", name, err))
+    }
+
+    /// Theme file: `inherits` names the theme to start from (`default` if not
+    /// given), then each style is a table like `keyword = { fg = "#c678dd", bold = true }`.
+    fn from_table(name: &str, table: &Table, depth: usize) -> Result<Theme, String> {
+        let mut theme = match table.get("inherits") {
+            Some(Value::String(base)) => Theme::load_inherited(base, depth + 1)?,
+            Some(_) => return Err("'inherits' must be the name of another theme".to_string()),
+            None => Theme::default(),
+        };
+        theme.name = name.to_string();
+
+        for (key, value) in table.iter().filter(|(key, _)| *key != "inherits") {
+            let style = parse_style(value).map_err(|err| format!("{} in '{}'", err, key))?;
+            match key.as_str() {
+                "text" => theme.text = style,
+                "status_bar" => theme.status_bar = style,
+                "message" => theme.message = style,
+                "selection" => theme.selection = style,
+                "search_match" => theme.search_match = style,
+                "matching_bracket" => theme.matching_bracket = style,
+                "unmatched_bracket" => theme.unmatched_bracket = style,
+                "long_line" => theme.long_line = style,
+                "whitespace" => theme.whitespace = style,
+                "cursor" => theme.cursor = style,
+                "gutter" => theme.gutter = style,
+                "keyword" => theme.keyword = style,
+                "type" => theme.type_name = style,
+                "string" => theme.string = style,
+                "number" => theme.number = style,
+                "comment" => theme.comment = style,
+                _ => return Err(format!("unknown style '{}'", key)),
+            }
+        }
+        Ok(theme)
+    }
+
+    pub fn token(&self, class: TokenClass) -> Style {
+        match class {
+            TokenClass::Normal => Style::default(),
+            TokenClass::Keyword => self.keyword,
+            TokenClass::Type => self.type_name,
+            TokenClass::String => self.string,
+            TokenClass::Number => self.number,
+            TokenClass::Comment => self.comment,
+        }
+    }
+
+    /// Same theme with the colors the terminal can show.
+    pub fn degrade(self, support: ColorSupport) -> Theme {
+        Theme {
+            text: self.text.degrade(support),
+            status_bar: self.status_bar.degrade(support),
+            message: self.message.degrade(support),
+            selection: self.selection.degrade(support),
+            search_match: self.search_match.degrade(support),
+            matching_bracket: self.matching_bracket.degrade(support),
+            unmatched_bracket: self.unmatched_bracket.degrade(support),
+            long_line: self.long_line.degrade(support),
+            whitespace: self.whitespace.degrade(support),
+            cursor: self.cursor.degrade(support),
+            gutter: self.gutter.degrade(support),
+            keyword: self.keyword.degrade(support),
+            type_name: self.type_name.degrade(support),
+            string: self.string.degrade(support),
+            number: self.number.degrade(support),
+            comment: self.comment.degrade(support),
+            ..self
+        }
+    }
+}
+
+fn parse_style(value: &Value) -> Result<Style, String> {
+    let Value::Table(table) = value else {
+        return Err("a style must be a table".to_string());
+    };
+
+    let mut style = Style::default();
+    for (key, value) in table {
+        let invalid = || format!("invalid value {} for '{}'", value, key);
+        let flag = || value.as_bool().ok_or_else(invalid);
+        match key.as_str() {
+            "fg" => style.fg = Some(value.as_str().and_then(parse_color).ok_or_else(invalid)?),
+            "bg" => style.bg = Some(value.as_str().and_then(parse_color).ok_or_else(invalid)?),
+            "bold" => style.bold = flag()?,
+            "dim" => style.dim = flag()?,
+            "italic" => style.italic = flag()?,
+            "underline" => style.underline = flag()?,
+            "reverse" => style.reverse = flag()?,
+            _ => return Err(format!("unknown attribute '{}'", key)),
+        }
+    }
+    Ok(style)
+}
+
+const ANSI_COLORS: [(&str, Color, u32); 16] = [
+    ("black", Color::Black, 0x000000),
+    ("red", Color::DarkRed, 0xcd0000),
+    ("green", Color::DarkGreen, 0x00cd00),
+    ("yellow", Color::DarkYellow, 0xcdcd00),
+    ("blue", Color::DarkBlue, 0x0000ee),
+    ("magenta", Color::DarkMagenta, 0xcd00cd),
+    ("cyan", Color::DarkCyan, 0x00cdcd),
+    ("white", Color::Grey, 0xe5e5e5),
+    ("bright_black", Color::DarkGrey, 0x7f7f7f),
+    ("bright_red", Color::Red, 0xff0000),
+    ("bright_green", Color::Green, 0x00ff00),
+    ("bright_yellow", Color::Yellow, 0xffff00),
+    ("bright_blue", Color::Blue, 0x5c5cff),
+    ("bright_magenta", Color::Magenta, 0xff00ff),
+    ("bright_cyan", Color::Cyan, 0x00ffff),
+    ("bright_white", Color::White, 0xffffff),
+];
+
+/// Colors are written as `#rrggbb` or as the name of one of the 16 terminal colors.
+fn parse_color(name: &str) -> Option<Color> {
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        return u32::from_str_radix(hex, 16).ok().map(rgb);
+    }
+    ANSI_COLORS
+        .iter()
+        .find(|(ansi_name, _, _)| *ansi_name == name)
+        .map(|(_, color, _)| *color)
+}
+
+/// Colors the terminal can show, from `COLORTERM` and `TERM`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorSupport {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColorSupport {
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorSupport::TrueColor
+        } else if !colorterm.is_empty() || term.contains("256color") {
+            ColorSupport::Ansi256
+        } else {
+            ColorSupport::Ansi16
+        }
+    }
+
+    fn convert(self, color: Color) -> Color {
+        let Color::Rgb { r, g, b } = color else {
+            return color;
+        };
+        match self {
+            ColorSupport::TrueColor => color,
+            ColorSupport::Ansi256 => Color::AnsiValue(ansi_256(r, g, b)),
+            ColorSupport::Ansi16 => nearest_ansi_16(r, g, b),
+        }
+    }
+}
+
+/// Closest color of the 6x6x6 cube or of the grey ramp of the 256 color palette.
+fn ansi_256(r: u8, g: u8, b: u8) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let level = |c: u8| {
+        (0..6)
+            .min_by_key(|&i| (LEVELS[i] as i32 - c as i32).abs())
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = (LEVELS[ri], LEVELS[gi], LEVELS[bi]);
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let grey_index = ((average.saturating_sub(8)) / 10).min(23) as u8;
+    let grey = 8 + grey_index * 10;
+
+    if distance((r, g, b), (grey, grey, grey)) < distance((r, g, b), cube) {
+        232 + grey_index
+    } else {
+        16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8
+    }
+}
+
+fn nearest_ansi_16(r: u8, g: u8, b: u8) -> Color {
+    ANSI_COLORS
+        .iter()
+        .min_by_key(|(_, _, hex)| {
+            distance((r, g, b), ((hex >> 16) as u8, (hex >> 8) as u8, *hex as u8))
+        })
+        .map(|(_, color, _)| *color)
+        .unwrap_or(Color::Reset)
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
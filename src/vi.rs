@@ -0,0 +1,586 @@
+use std::fmt::{Display, Formatter};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::coords::Coordinates;
+use crate::directions::Direction;
+use crate::words::{
+    char_at, char_before, next_word_start, step, word_boundary, word_end, CharClass,
+};
+
+/// Largest count, so that a mistyped one can't overflow the line numbers
+const MAX_COUNT: usize = 99_999_999;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Down,
+    Up,
+    Right,
+    WordStart,
+    WordBack,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    FirstLine,
+    LastLine,
+    /// 1-based line number, from `5G` or `5gg`
+    Line(usize),
+}
+
+impl Motion {
+    /// Motions that make operators work on whole lines.
+    pub fn is_linewise(&self) -> bool {
+        matches!(
+            self,
+            Motion::Down | Motion::Up | Motion::FirstLine | Motion::LastLine | Motion::Line(_)
+        )
+    }
+
+    /// Motions whose target character is included when used with an operator.
+    pub fn is_inclusive(&self) -> bool {
+        matches!(self, Motion::WordEnd)
+    }
+}
+
+/// Inner text objects, the text inside a word, quotes or brackets.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextObject {
+    Word,
+    Quote(char),
+    Pair(char, char),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Target {
+    Motion(Motion),
+    Object(TextObject),
+    /// The operator repeated, as in `dd`
+    Line,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InsertAt {
+    Cursor,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ViCommand {
+    /// Key handled by the default keymap
+    Key(KeyEvent),
+    Move(Motion, usize),
+    Operate(Operator, Target, usize),
+    Insert(InsertAt),
+    Paste {
+        before: bool,
+        count: usize,
+    },
+    VisualStart,
+    VisualSelect(TextObject),
+    VisualOperate(Operator),
+    Normal,
+    Repeat(Option<usize>),
+    CommandLine,
+}
+
+impl ViCommand {
+    pub fn enters_insert(&self) -> bool {
+        matches!(
+            self,
+            ViCommand::Insert(_)
+                | ViCommand::Operate(Operator::Change, _, _)
+                | ViCommand::VisualOperate(Operator::Change)
+        )
+    }
+
+    fn with_count(self, count: usize) -> Self {
+        match self {
+            ViCommand::Operate(op, target, _) => ViCommand::Operate(op, target, count),
+            ViCommand::Paste { before, .. } => ViCommand::Paste { before, count },
+            command => command,
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct Register {
+    pub text: String,
+    pub linewise: bool,
+    /// Copied from a block selection, a line for each row
+    pub block: bool,
+}
+
+enum Parse<T> {
+    Complete(T),
+    Incomplete,
+    Invalid,
+}
+
+/// Modal key layer: turns keys into commands for the editor. The keys typed in
+/// Insert mode are passed to the default keymap.
+pub struct Vi {
+    mode: Mode,
+    pending: Vec<char>,
+    last_change: Option<(ViCommand, Vec<KeyEvent>)>,
+    recording: bool,
+}
+
+impl Vi {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Normal,
+            pending: vec![],
+            last_change: None,
+            recording: false,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Last change and the keys typed in Insert mode after it, used by `.`.
+    /// The count given to `.` replaces the one of the change.
+    pub fn last_change(&self, count: Option<usize>) -> Option<(ViCommand, Vec<KeyEvent>)> {
+        self.last_change.clone().map(|(command, keys)| match count {
+            Some(count) => (command.with_count(count), keys),
+            None => (command, keys),
+        })
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<ViCommand> {
+        match self.mode {
+            Mode::Insert => {
+                if key.code == KeyCode::Esc {
+                    self.mode = Mode::Normal;
+                    self.recording = false;
+                    return Some(ViCommand::Normal);
+                }
+                if self.recording {
+                    if let Some((_, keys)) = self.last_change.as_mut() {
+                        keys.push(key);
+                    }
+                }
+                Some(ViCommand::Key(key))
+            }
+            Mode::Normal | Mode::Visual => self.handle_command_key(key),
+        }
+    }
+
+    fn handle_command_key(&mut self, key: KeyEvent) -> Option<ViCommand> {
+        let ch = match key.code {
+            KeyCode::Esc => {
+                self.pending.clear();
+                if self.mode == Mode::Visual {
+                    self.mode = Mode::Normal;
+                    return Some(ViCommand::Normal);
+                }
+                return None;
+            }
+            KeyCode::Char(ch)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                ch
+            }
+            KeyCode::Backspace => 'h',
+            KeyCode::Enter => 'j',
+            KeyCode::Delete => 'x',
+            KeyCode::Char(_)
+            | KeyCode::Up
+            | KeyCode::Down
+            | KeyCode::Left
+            | KeyCode::Right
+            | KeyCode::Home
+            | KeyCode::End
+            | KeyCode::PageUp
+            | KeyCode::PageDown => {
+                self.pending.clear();
+                return Some(ViCommand::Key(key));
+            }
+            // indent and outdent the selection
+            KeyCode::Tab | KeyCode::BackTab if self.mode == Mode::Visual => {
+                self.pending.clear();
+                return Some(ViCommand::Key(key));
+            }
+            _ => return None,
+        };
+
+        self.pending.push(ch);
+        let parsed = if self.mode == Mode::Visual {
+            parse_visual(&self.pending)
+        } else {
+            parse_normal(&self.pending)
+        };
+
+        match parsed {
+            Parse::Complete(command) => {
+                self.pending.clear();
+                self.update_mode(command);
+                Some(command)
+            }
+            Parse::Incomplete => None,
+            Parse::Invalid => {
+                self.pending.clear();
+                None
+            }
+        }
+    }
+
+    fn update_mode(&mut self, command: ViCommand) {
+        let enters_insert = command.enters_insert();
+
+        match command {
+            ViCommand::Insert(_)
+            | ViCommand::Operate(Operator::Delete | Operator::Change, _, _)
+            | ViCommand::Paste { .. } => {
+                self.last_change = Some((command, vec![]));
+                self.recording = enters_insert;
+            }
+            _ => (),
+        }
+
+        self.mode = match command {
+            _ if enters_insert => Mode::Insert,
+            ViCommand::VisualStart => Mode::Visual,
+            ViCommand::VisualOperate(_) | ViCommand::Normal => Mode::Normal,
+            _ => self.mode,
+        };
+    }
+}
+
+fn take_count(keys: &[char]) -> (Option<usize>, &[char]) {
+    if keys.first() == Some(&'0') {
+        return (None, keys);
+    }
+    let digits = keys.iter().take_while(|ch| ch.is_ascii_digit()).count();
+    if digits == 0 {
+        return (None, keys);
+    }
+    // only too many digits fail to parse
+    let count = keys[..digits]
+        .iter()
+        .collect::<String>()
+        .parse()
+        .map_or(MAX_COUNT, |count: usize| count.min(MAX_COUNT));
+    (Some(count), &keys[digits..])
+}
+
+fn parse_normal(keys: &[char]) -> Parse<ViCommand> {
+    let (count, rest) = take_count(keys);
+    let times = count.unwrap_or(1);
+
+    let Some(&first) = rest.first() else {
+        return Parse::Incomplete;
+    };
+
+    let command = match first {
+        'd' | 'c' | 'y' => return parse_operator(first, count, &rest[1..]),
+        'x' => ViCommand::Operate(Operator::Delete, Target::Motion(Motion::Right), times),
+        'X' => ViCommand::Operate(Operator::Delete, Target::Motion(Motion::Left), times),
+        'D' => ViCommand::Operate(Operator::Delete, Target::Motion(Motion::LineEnd), 1),
+        'C' => ViCommand::Operate(Operator::Change, Target::Motion(Motion::LineEnd), 1),
+        'i' => ViCommand::Insert(InsertAt::Cursor),
+        'a' => ViCommand::Insert(InsertAt::After),
+        'I' => ViCommand::Insert(InsertAt::LineStart),
+        'A' => ViCommand::Insert(InsertAt::LineEnd),
+        'o' => ViCommand::Insert(InsertAt::LineBelow),
+        'O' => ViCommand::Insert(InsertAt::LineAbove),
+        'p' => ViCommand::Paste {
+            before: false,
+            count: times,
+        },
+        'P' => ViCommand::Paste {
+            before: true,
+            count: times,
+        },
+        'v' => ViCommand::VisualStart,
+        '.' => ViCommand::Repeat(count),
+        ':' => ViCommand::CommandLine,
+        _ => {
+            return match parse_motion(rest, count) {
+                Parse::Complete(motion) => Parse::Complete(ViCommand::Move(motion, times)),
+                Parse::Incomplete => Parse::Incomplete,
+                Parse::Invalid => Parse::Invalid,
+            }
+        }
+    };
+
+    if rest.len() > 1 {
+        return Parse::Invalid;
+    }
+    Parse::Complete(command)
+}
+
+fn parse_operator(op: char, count: Option<usize>, keys: &[char]) -> Parse<ViCommand> {
+    let operator = match op {
+        'd' => Operator::Delete,
+        'c' => Operator::Change,
+        _ => Operator::Yank,
+    };
+
+    let (motion_count, rest) = take_count(keys);
+    let times = count
+        .unwrap_or(1)
+        .saturating_mul(motion_count.unwrap_or(1))
+        .min(MAX_COUNT);
+
+    match rest {
+        [] => Parse::Incomplete,
+        [ch] if *ch == op => Parse::Complete(ViCommand::Operate(operator, Target::Line, times)),
+        ['i', ..] => match parse_text_object(&rest[1..]) {
+            Parse::Complete(object) => {
+                Parse::Complete(ViCommand::Operate(operator, Target::Object(object), 1))
+            }
+            Parse::Incomplete => Parse::Incomplete,
+            Parse::Invalid => Parse::Invalid,
+        },
+        _ => match parse_motion(rest, motion_count.or(count)) {
+            // like in vi, `cw` changes until the end of the word
+            Parse::Complete(Motion::WordStart) if operator == Operator::Change => Parse::Complete(
+                ViCommand::Operate(operator, Target::Motion(Motion::WordEnd), times),
+            ),
+            Parse::Complete(motion) => {
+                Parse::Complete(ViCommand::Operate(operator, Target::Motion(motion), times))
+            }
+            Parse::Incomplete => Parse::Incomplete,
+            Parse::Invalid => Parse::Invalid,
+        },
+    }
+}
+
+fn parse_visual(keys: &[char]) -> Parse<ViCommand> {
+    let (count, rest) = take_count(keys);
+
+    match rest {
+        [] => Parse::Incomplete,
+        ['d' | 'x'] => Parse::Complete(ViCommand::VisualOperate(Operator::Delete)),
+        ['c'] => Parse::Complete(ViCommand::VisualOperate(Operator::Change)),
+        ['y'] => Parse::Complete(ViCommand::VisualOperate(Operator::Yank)),
+        ['v'] => Parse::Complete(ViCommand::Normal),
+        ['i', ..] => match parse_text_object(&rest[1..]) {
+            Parse::Complete(object) => Parse::Complete(ViCommand::VisualSelect(object)),
+            Parse::Incomplete => Parse::Incomplete,
+            Parse::Invalid => Parse::Invalid,
+        },
+        _ => match parse_motion(rest, count) {
+            Parse::Complete(motion) => Parse::Complete(ViCommand::Move(motion, count.unwrap_or(1))),
+            Parse::Incomplete => Parse::Incomplete,
+            Parse::Invalid => Parse::Invalid,
+        },
+    }
+}
+
+fn parse_motion(keys: &[char], count: Option<usize>) -> Parse<Motion> {
+    let motion = match keys {
+        ['h'] => Motion::Left,
+        ['j'] => Motion::Down,
+        ['k'] => Motion::Up,
+        ['l' | ' '] => Motion::Right,
+        ['w'] => Motion::WordStart,
+        ['b'] => Motion::WordBack,
+        ['e'] => Motion::WordEnd,
+        ['0'] => Motion::LineStart,
+        ['$'] => Motion::LineEnd,
+        ['g'] => return Parse::Incomplete,
+        ['g', 'g'] | ['G'] => match count {
+            Some(line) => Motion::Line(line),
+            None if keys[0] == 'G' => Motion::LastLine,
+            None => Motion::FirstLine,
+        },
+        _ => return Parse::Invalid,
+    };
+    Parse::Complete(motion)
+}
+
+fn parse_text_object(keys: &[char]) -> Parse<TextObject> {
+    let object = match keys {
+        [] => return Parse::Incomplete,
+        ['w'] => TextObject::Word,
+        [quote @ ('"' | '\'' | '`')] => TextObject::Quote(*quote),
+        ['(' | ')' | 'b'] => TextObject::Pair('(', ')'),
+        ['[' | ']'] => TextObject::Pair('[', ']'),
+        ['{' | '}' | 'B'] => TextObject::Pair('{', '}'),
+        ['<' | '>'] => TextObject::Pair('<', '>'),
+        _ => return Parse::Invalid,
+    };
+    Parse::Complete(object)
+}
+
+/// Position reached by applying `motion` `count` times from `from`.
+pub fn motion_target(
+    rows: &[String],
+    from: Coordinates<usize>,
+    motion: Motion,
+    count: usize,
+) -> Coordinates<usize> {
+    let last_line = rows.len().saturating_sub(1);
+    let first_non_blank = |y: usize| {
+        let row = &rows[y];
+        Coordinates::new(row.len() - row.trim_start().len(), y)
+    };
+    let in_line = |y: usize| Coordinates::new(from.x().min(rows[y].len()), y);
+
+    let mut pos = from;
+    match motion {
+        Motion::Left | Motion::Right => {
+            let direction = if motion == Motion::Left {
+                Direction::Left
+            } else {
+                Direction::Right
+            };
+            for _ in 0..count {
+                match step(rows, pos, direction) {
+                    Some(next) if next.y() == pos.y() => pos = next,
+                    _ => break,
+                }
+            }
+        }
+        Motion::Down => pos = in_line((from.y() + count).min(last_line)),
+        Motion::Up => pos = in_line(from.y().saturating_sub(count)),
+        Motion::WordStart => {
+            for _ in 0..count {
+                let next = next_word_start(rows, pos);
+                if next == pos {
+                    break;
+                }
+                pos = next;
+            }
+        }
+        Motion::WordBack => {
+            for _ in 0..count {
+                let next = word_boundary(rows, pos, Direction::Left);
+                if next == pos {
+                    break;
+                }
+                pos = next;
+            }
+        }
+        Motion::WordEnd => {
+            for _ in 0..count {
+                let next = word_end(rows, pos);
+                if next == pos {
+                    break;
+                }
+                pos = next;
+            }
+        }
+        Motion::LineStart => pos = Coordinates::new(0, from.y()),
+        Motion::LineEnd => pos = Coordinates::new(rows[from.y()].len(), from.y()),
+        Motion::FirstLine => pos = first_non_blank(0),
+        Motion::LastLine => pos = first_non_blank(last_line),
+        Motion::Line(line) => pos = first_non_blank(line.saturating_sub(1).min(last_line)),
+    }
+    pos
+}
+
+/// Start (included) and end (excluded) of `object` around `pos`.
+pub fn text_object_range(
+    rows: &[String],
+    pos: Coordinates<usize>,
+    object: TextObject,
+) -> Option<(Coordinates<usize>, Coordinates<usize>)> {
+    match object {
+        TextObject::Word => {
+            let row = rows.get(pos.y())?;
+            let class = CharClass::of(row.get(pos.x()..)?.chars().next()?);
+
+            let mut start = pos;
+            while let Some(ch) = char_before(rows, start).filter(|_| start.x() > 0) {
+                if CharClass::of(ch) != class {
+                    break;
+                }
+                start = step(rows, start, Direction::Left)?;
+            }
+            let mut end = pos;
+            while end.x() < row.len() && char_at(rows, end).map(CharClass::of) == Some(class) {
+                end = step(rows, end, Direction::Right)?;
+            }
+            Some((start, end))
+        }
+        TextObject::Quote(quote) => {
+            let row = rows.get(pos.y())?;
+            let quotes: Vec<usize> = row.match_indices(quote).map(|(i, _)| i).collect();
+            quotes
+                .chunks_exact(2)
+                .find(|pair| pos.x() <= pair[1])
+                .map(|pair| {
+                    (
+                        Coordinates::new(pair[0] + 1, pos.y()),
+                        Coordinates::new(pair[1], pos.y()),
+                    )
+                })
+        }
+        TextObject::Pair(open, close) => {
+            let start = find_unmatched(rows, pos, open, close, Direction::Left)?;
+            let after_open = step(rows, start, Direction::Right)?;
+            let end = find_unmatched(rows, after_open, open, close, Direction::Right)?;
+            Some((after_open, end))
+        }
+    }
+}
+
+/// Finds the `open` (going left) or `close` (going right) bracket that is not
+/// matched between it and `from`. Going left, a bracket under `from` counts.
+fn find_unmatched(
+    rows: &[String],
+    from: Coordinates<usize>,
+    open: char,
+    close: char,
+    direction: Direction,
+) -> Option<Coordinates<usize>> {
+    let (wanted, nested) = if direction == Direction::Left {
+        (open, close)
+    } else {
+        (close, open)
+    };
+
+    let mut depth = 0;
+    let mut pos = from;
+    if direction == Direction::Left && char_at(rows, pos) == Some(close) {
+        pos = step(rows, pos, Direction::Left)?;
+    }
+    loop {
+        let ch = char_at(rows, pos)?;
+        if ch == wanted {
+            if depth == 0 {
+                return Some(pos);
+            }
+            depth -= 1;
+        } else if ch == nested {
+            depth += 1;
+        }
+        pos = step(rows, pos, direction)?;
+    }
+}
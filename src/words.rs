@@ -0,0 +1,126 @@
+use crate::coords::Coordinates;
+use crate::directions::Direction;
+
+/// Kind of character used to find word boundaries. A word is a run of characters
+/// of the same class, the end of a line counts as whitespace.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CharClass {
+    Word,
+    Punctuation,
+    Whitespace,
+}
+
+impl CharClass {
+    pub fn of(ch: char) -> Self {
+        if ch.is_alphanumeric() || ch == '_' {
+            CharClass::Word
+        } else if ch.is_whitespace() {
+            CharClass::Whitespace
+        } else {
+            CharClass::Punctuation
+        }
+    }
+}
+
+/// Character under `pos`, `'\n'` at the end of a line.
+pub fn char_at(rows: &[String], pos: Coordinates<usize>) -> Option<char> {
+    let row = rows.get(pos.y())?;
+    match row.get(pos.x()..)?.chars().next() {
+        Some(ch) => Some(ch),
+        None => Some('\n'),
+    }
+}
+
+/// Character before `pos`, `'\n'` at the beginning of a line.
+pub fn char_before(rows: &[String], pos: Coordinates<usize>) -> Option<char> {
+    step(rows, pos, Direction::Left).and_then(|prev| char_at(rows, prev))
+}
+
+/// Moves one character left or right, going through line ends.
+/// Returns `None` at the beginning and end of the file.
+pub fn step(
+    rows: &[String],
+    pos: Coordinates<usize>,
+    direction: Direction,
+) -> Option<Coordinates<usize>> {
+    let row = rows.get(pos.y())?;
+    match direction {
+        Direction::Right => match row.get(pos.x()..)?.chars().next() {
+            Some(ch) => Some(Coordinates::new(pos.x() + ch.len_utf8(), pos.y())),
+            None if pos.y() + 1 < rows.len() => Some(Coordinates::new(0, pos.y() + 1)),
+            None => None,
+        },
+        Direction::Left => match row.get(..pos.x())?.chars().next_back() {
+            Some(ch) => Some(Coordinates::new(pos.x() - ch.len_utf8(), pos.y())),
+            None if pos.y() > 0 => Some(Coordinates::new(rows[pos.y() - 1].len(), pos.y() - 1)),
+            None => None,
+        },
+        Direction::Up | Direction::Down => None,
+    }
+}
+
+/// Position reached by skipping the whitespace and then the word next to `from`,
+/// like Ctrl-Left and Ctrl-Right.
+pub fn word_boundary(
+    rows: &[String],
+    from: Coordinates<usize>,
+    direction: Direction,
+) -> Coordinates<usize> {
+    let class_next_to = |pos: Coordinates<usize>| {
+        let ch = match direction {
+            Direction::Left => char_before(rows, pos),
+            _ => char_at(rows, pos),
+        };
+        ch.map(CharClass::of)
+    };
+
+    let mut pos = from;
+    while class_next_to(pos) == Some(CharClass::Whitespace) {
+        match step(rows, pos, direction) {
+            Some(next) => pos = next,
+            None => return pos,
+        }
+    }
+
+    let class = class_next_to(pos);
+    while class.is_some() && class_next_to(pos) == class {
+        match step(rows, pos, direction) {
+            Some(next) => pos = next,
+            None => return pos,
+        }
+    }
+
+    pos
+}
+
+/// Start of the next word after `from`, like vi's `w`.
+pub fn next_word_start(rows: &[String], from: Coordinates<usize>) -> Coordinates<usize> {
+    let class_at = |pos: Coordinates<usize>| char_at(rows, pos).map(CharClass::of);
+
+    let mut pos = from;
+    let class = class_at(pos);
+    if class != Some(CharClass::Whitespace) {
+        while class_at(pos) == class {
+            match step(rows, pos, Direction::Right) {
+                Some(next) => pos = next,
+                None => return pos,
+            }
+        }
+    }
+    while class_at(pos) == Some(CharClass::Whitespace) {
+        match step(rows, pos, Direction::Right) {
+            Some(next) => pos = next,
+            None => return pos,
+        }
+    }
+    pos
+}
+
+/// Last character of the word after `from`, like vi's `e`.
+pub fn word_end(rows: &[String], from: Coordinates<usize>) -> Coordinates<usize> {
+    let Some(next) = step(rows, from, Direction::Right) else {
+        return from;
+    };
+    let end = word_boundary(rows, next, Direction::Right);
+    step(rows, end, Direction::Left).unwrap_or(end)
+}